@@ -1,19 +1,94 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use clap::Subcommand;
 
-use crate::services::bundle::{generate_bundle_config, zip_bundle};
+use crate::config::app::Config;
+use crate::config::bundle::PlatformTarget;
+use crate::models::bundle::WriteOptions;
+use crate::services::bundle::{
+    Options, check_bundle, generate_bundle_config, list_bundle, zip_bundle,
+};
+use crate::services::watch::watch_bundle;
 
 #[derive(Subcommand, Debug)]
 pub enum BundleCmd {
     /// Initialize a Bundle configuration file
     Init,
     /// Create a Bundle from the configuration file
-    Create,
+    Create {
+        /// Directory the per-target bundles are written to
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Only bundle this target (ctr, hac or cafe), can be repeated
+        #[arg(long = "target")]
+        targets: Vec<PlatformTarget>,
+        /// Show what would be bundled instead of writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip checking the Lua sources before bundling
+        #[arg(long)]
+        no_check: bool,
+        /// Only rebuild the entries of files changed since the last incremental build
+        #[arg(long)]
+        incremental: bool,
+        /// Compress as fast as possible, ignoring the compression rules
+        #[arg(long)]
+        fast: bool,
+    },
+    /// List the files each target's Bundle would include, and why others are left out
+    #[command(visible_alias = "ls")]
+    List {
+        /// Only list this target (ctr, hac or cafe), can be repeated
+        #[arg(long = "target")]
+        targets: Vec<PlatformTarget>,
+    },
+    /// Check the Lua sources for syntax errors and APIs the targets don't support
+    Check {
+        /// Only check for this target (ctr, hac or cafe), can be repeated
+        #[arg(long = "target")]
+        targets: Vec<PlatformTarget>,
+        /// Show which of the APIs and asset formats used work on each target
+        #[arg(long)]
+        compat: bool,
+    },
+    /// Rebuild the Bundle whenever the sources, icons or configuration change
+    Watch {
+        /// Only bundle this target (ctr, hac or cafe), can be repeated
+        #[arg(long = "target")]
+        targets: Vec<PlatformTarget>,
+        /// Connection name or address to send and launch each packaged build on
+        #[arg(long)]
+        send: Option<String>,
+        /// Compress as fast as possible, ignoring the compression rules
+        #[arg(long)]
+        fast: bool,
+    },
 }
 
-pub fn handle_bundle(command: BundleCmd) -> Result<()> {
+pub fn handle_bundle(command: BundleCmd, config: Config) -> Result<()> {
     match command {
         BundleCmd::Init => generate_bundle_config(),
-        BundleCmd::Create => zip_bundle(),
+        BundleCmd::Create {
+            out_dir,
+            targets,
+            dry_run,
+            no_check,
+            incremental,
+            fast,
+        } => zip_bundle(Options {
+            out_dir,
+            targets,
+            dry_run,
+            check: !no_check,
+            write: WriteOptions { incremental, fast },
+        }),
+        BundleCmd::List { targets } => list_bundle(targets),
+        BundleCmd::Check { targets, compat } => check_bundle(targets, compat),
+        BundleCmd::Watch {
+            targets,
+            send,
+            fast,
+        } => watch_bundle(&config, targets, send, fast),
     }
 }
@@ -1,13 +1,27 @@
 use anyhow::Result;
 use clap::Subcommand;
-use std::net::Ipv4Addr;
 
-use crate::config::app::Config;
+use crate::config::app::{Config, Connection};
+use crate::config::bundle::PlatformTarget;
+use crate::services::discover::discover_devices;
 
 #[derive(Subcommand, Debug)]
 pub enum ConfigCmd {
     /// Add a new connection
-    Add { name: String, addr: Ipv4Addr },
+    Add {
+        name: String,
+        /// IPv4 or IPv6 address, or a hostname
+        address: String,
+        /// Port for debug attach, instead of the default
+        #[arg(long)]
+        port: Option<u16>,
+        /// The console this connection is (ctr, hac or cafe)
+        #[arg(long)]
+        platform: Option<PlatformTarget>,
+        /// Free-form notes shown when listing connections
+        #[arg(long)]
+        notes: Option<String>,
+    },
     /// Remove a connection
     #[command(visible_alias = "rm", aliases = ["delete", "del"])]
     Remove { name: String },
@@ -16,13 +30,34 @@ pub enum ConfigCmd {
     List,
     /// Open the directory to the config file
     Open,
+    /// Search the local network for devices and save them
+    Discover {
+        /// How long to wait for replies, in seconds
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
 }
 
 pub fn handle_connection(command: ConfigCmd, mut config: Config) -> Result<()> {
     match command {
-        ConfigCmd::Add { name, addr } => config.add(&name, addr),
+        ConfigCmd::Add {
+            name,
+            address,
+            port,
+            platform,
+            notes,
+        } => {
+            let connection = Connection {
+                port,
+                platform,
+                notes,
+                ..Connection::new(address)
+            };
+            config.add(&name, connection)
+        }
         ConfigCmd::Remove { name } => config.remove(&name),
         ConfigCmd::List => config.list(),
         ConfigCmd::Open => config.reveal(),
+        ConfigCmd::Discover { timeout } => discover_devices(&mut config, timeout),
     }
 }
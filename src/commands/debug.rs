@@ -1,17 +1,18 @@
-use std::{
-    fs::File,
-    io::{Write, stdout},
-    net::Ipv4Addr,
-    path::PathBuf,
-};
+use std::{fs::OpenOptions, path::PathBuf};
 
 use anyhow::Result;
 use clap::Subcommand;
-use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
 
 use crate::config::app::Config;
-use crate::models::socket::Socket;
+use crate::config::bundle::{BundleConfig, PlatformTarget};
+use crate::models::bundle::OUTPUT_DIR;
+use crate::models::socket::Level;
+use crate::models::traceback::SourceMap;
 use crate::platforms::addr2line::find_candidate;
+use crate::platforms::annotate::Annotator;
+use crate::services::attach::{Filter, Output, attach};
+use crate::services::console::{Console, Remote};
 
 #[derive(Subcommand)]
 pub enum DebugCmd {
@@ -19,57 +20,128 @@ pub enum DebugCmd {
     Attach {
         address: String,
         logfile: Option<PathBuf>,
+        /// ELF used to symbolize crash addresses in the output
+        #[arg(long)]
+        elf: Option<PathBuf>,
+        /// Override the platform detected from the ELF header (ctr, hac or cafe)
+        #[arg(long, requires = "elf")]
+        platform: Option<PlatformTarget>,
+        /// Lines of source to print around each Lua traceback frame
+        #[arg(long, default_value_t = 0)]
+        context: usize,
+        /// Directory the bundles were written to, for mapping minified Lua back
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Keep reconnecting after the target disconnects or restarts
+        #[arg(long, visible_alias = "reconnect")]
+        watch: bool,
+        /// Send Lua typed at the prompt to the target's console
+        #[arg(short, long)]
+        interactive: bool,
+        /// Only show structured messages at or above this level
+        #[arg(long)]
+        level: Option<Level>,
+        /// Only show structured messages whose payload matches this regex
+        #[arg(long)]
+        grep: Option<Regex>,
+        /// Only show structured messages with this tag, can be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
     },
     /// Debug a local binary using addr2line
     Addr2line {
         filepath: String,
         addresses: Vec<String>,
+        /// Use the devkitPro addr2line binary instead of the built-in resolver
+        #[arg(long)]
+        external: bool,
+        /// Override the platform detected from the ELF header (ctr, hac or cafe)
+        #[arg(long)]
+        platform: Option<PlatformTarget>,
     },
 }
 
-fn create_spinner() -> Result<ProgressBar> {
-    let progress_bar = ProgressBar::new_spinner();
-    let template = ProgressStyle::with_template("Attaching... {spinner}")?
-        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]);
-    progress_bar.set_style(template);
-    Ok(progress_bar)
-}
-
 pub fn handle_debug(command: DebugCmd, config: Config) -> Result<()> {
     match command {
-        DebugCmd::Attach { address, logfile } => {
-            let target = match config.get(&address) {
-                Some(addr) => *addr,
-                None => address.parse::<Ipv4Addr>()?,
-            };
-            let progress = create_spinner()?;
-            progress.enable_steady_tick(std::time::Duration::from_millis(120));
-            let mut socket = match Socket::new((target, config.get_port())) {
-                Ok(socket) => {
-                    progress.finish_with_message("Attached.");
-                    socket
-                }
-                Err(e) => {
-                    progress.abandon_with_message("Failed to connect.");
-                    return Err(e.into());
+        DebugCmd::Attach {
+            address,
+            logfile,
+            elf,
+            platform,
+            context,
+            out_dir,
+            watch,
+            interactive,
+            level,
+            grep,
+            tags,
+        } => {
+            let annotator = elf
+                .map(|path| Annotator::new(&path, platform))
+                .transpose()?;
+            let sources = match BundleConfig::exists() {
+                true => {
+                    let config = BundleConfig::load()?;
+                    let root = std::env::current_dir()?;
+                    let out_dir = out_dir.unwrap_or_else(|| PathBuf::from(OUTPUT_DIR));
+                    Some(SourceMap::new(&root, &config.build.source, &out_dir)?)
                 }
+                false => None,
             };
-            let mut file = logfile.map(File::create).transpose()?;
-            while let Some(data) = socket.read()? {
-                stdout().write_all(data)?;
-                if let Some(f) = file.as_mut() {
-                    f.write_all(data)?;
-                }
+            // sessions from earlier runs are kept when watching
+            let file = logfile
+                .map(|path| {
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .append(watch)
+                        .truncate(!watch)
+                        .open(path)
+                })
+                .transpose()?;
+            let filter = Filter { level, grep, tags };
+            let mut output = Output::new(file, annotator, sources, context, filter);
+            let address = config.resolve(&address).socket_addr(config.get_port())?;
+            let remote = Remote::default();
+
+            if !interactive {
+                return attach(address, &mut output, watch, &remote);
+            }
+
+            let mut console = Console::new()?;
+            if let Some(printer) = console.printer() {
+                output.set_printer(printer);
+            }
+            let reader = {
+                let remote = remote.clone();
+                let interrupter = console.interrupter();
+                std::thread::spawn(move || {
+                    let result = attach(address, &mut output, watch, &remote);
+                    interrupter.interrupt();
+                    result
+                })
+            };
+            console.run(&remote, &reader)?;
+
+            // the reader is left running if the console was closed first
+            if reader.is_finished() {
+                reader
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("Reader thread panicked"))??;
             }
         }
         DebugCmd::Addr2line {
             filepath,
             addresses,
+            external,
+            platform,
         } => {
             let filepath = std::path::absolute(filepath)?;
-            let candidate = find_candidate(&filepath)?;
-            if let Some(candidate) = candidate {
+            let candidate = find_candidate(&filepath, platform)?;
+            if external {
                 candidate.command(&filepath, &addresses)?;
+            } else {
+                candidate.symbolize(&filepath, &addresses)?;
             }
         }
     }
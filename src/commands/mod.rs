@@ -1,3 +1,4 @@
 pub mod bundle;
 pub mod conn;
 pub mod debug;
+pub mod send;
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::config::app::Config;
+use crate::config::bundle::PlatformTarget;
+use crate::services::send::send_file;
+
+#[derive(Args, Debug)]
+pub struct SendCmd {
+    /// Connection name or address of the console
+    target: String,
+    /// The 3DSX, NRO, RPX or WUHB to launch
+    file: PathBuf,
+    /// Arguments passed to the launched program
+    #[arg(last = true)]
+    args: Vec<String>,
+    /// Override the platform detected from the file (ctr, hac or cafe)
+    #[arg(long)]
+    platform: Option<PlatformTarget>,
+    /// Print the program's stdio once launched (nxlink only)
+    #[arg(short, long)]
+    server: bool,
+}
+
+pub fn handle_send(command: SendCmd, config: Config) -> Result<()> {
+    send_file(
+        &config,
+        &command.target,
+        &command.file,
+        &command.args,
+        command.platform,
+        command.server,
+    )
+}
@@ -1,12 +1,14 @@
 use std::collections::BTreeMap;
 use std::fs;
-use std::net::Ipv4Addr;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
 
 use anyhow::{Result, bail};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
+use crate::config::bundle::PlatformTarget;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SocketConfig {
     default_port: u16,
@@ -18,9 +20,75 @@ impl Default for SocketConfig {
     }
 }
 
+/// Connections used to be stored as a bare IPv4 address string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConnectionEntry {
+    Legacy(String),
+    Table {
+        address: String,
+        port: Option<u16>,
+        platform: Option<PlatformTarget>,
+        notes: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ConnectionEntry")]
+pub struct Connection {
+    /// IPv4, IPv6 or hostname
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<PlatformTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl From<ConnectionEntry> for Connection {
+    fn from(entry: ConnectionEntry) -> Self {
+        match entry {
+            ConnectionEntry::Legacy(address) => Self::new(address),
+            ConnectionEntry::Table {
+                address,
+                port,
+                platform,
+                notes,
+            } => Self {
+                address,
+                port,
+                platform,
+                notes,
+            },
+        }
+    }
+}
+
+impl Connection {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            port: None,
+            platform: None,
+            notes: None,
+        }
+    }
+
+    /// Resolves the address, using `port` unless the connection overrides it
+    pub fn socket_addr(&self, port: u16) -> Result<SocketAddr> {
+        let port = self.port.unwrap_or(port);
+        let resolved = (self.address.as_str(), port).to_socket_addrs();
+        match resolved.ok().and_then(|mut addrs| addrs.next()) {
+            Some(address) => Ok(address),
+            None => bail!("Could not resolve '{}'", self.address),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
-    connections: BTreeMap<String, Ipv4Addr>,
+    connections: BTreeMap<String, Connection>,
     socket: SocketConfig,
 }
 
@@ -30,6 +98,16 @@ const APPLICATION: &str = "nestcli";
 
 const FILE_NAME: &str = "config.toml";
 
+/// Whether any connection is still stored as a bare address
+fn has_legacy_connections(content: &str) -> Result<bool> {
+    let table: toml::Table = toml::from_str(content)?;
+    let legacy = match table.get("connections").and_then(|c| c.as_table()) {
+        Some(connections) => connections.values().any(|value| value.is_str()),
+        None => false,
+    };
+    Ok(legacy)
+}
+
 impl Config {
     pub fn path() -> Result<PathBuf> {
         let dirs = ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION);
@@ -47,11 +125,11 @@ impl Config {
         Ok(())
     }
 
-    pub fn add(&mut self, name: &str, address: Ipv4Addr) -> Result<()> {
+    pub fn add(&mut self, name: &str, connection: Connection) -> Result<()> {
         if name.is_empty() {
             bail!("Name cannot be empty");
         }
-        let value = self.connections.insert(name.to_string(), address);
+        let value = self.connections.insert(name.to_string(), connection);
         println!(
             "Connection '{name}' {}",
             value.map_or("added", |_| "updated")
@@ -71,14 +149,31 @@ impl Config {
         Ok(())
     }
 
-    pub fn get(&self, name: &str) -> Option<&Ipv4Addr> {
+    pub fn get(&self, name: &str) -> Option<&Connection> {
         self.connections.get(name)
     }
 
+    /// Looks up a connection by name, otherwise `target` is taken as the address
+    pub fn resolve(&self, target: &str) -> Connection {
+        match self.get(target) {
+            Some(connection) => connection.clone(),
+            None => Connection::new(target),
+        }
+    }
+
     pub fn list(&self) -> Result<()> {
-        println!("{:<10} Address", "Name");
-        for (name, addr) in &self.connections {
-            println!("{name:<10} {addr}");
+        println!(
+            "{:<10} {:<24} {:<6} {:<8} Notes",
+            "Name", "Address", "Port", "Platform"
+        );
+        for (name, connection) in &self.connections {
+            let port = connection.port.unwrap_or(self.get_port());
+            let platform = connection.platform.map_or("-".into(), |p| p.to_string());
+            let notes = connection.notes.as_deref().unwrap_or("");
+            println!(
+                "{name:<10} {:<24} {port:<6} {platform:<8} {notes}",
+                connection.address
+            );
         }
         Ok(())
     }
@@ -89,7 +184,12 @@ impl Config {
             return Ok(Self::default());
         }
         let content = fs::read_to_string(path)?;
-        let config = toml::from_str(&content)?;
+        let config: Self = toml::from_str(&content)?;
+
+        if has_legacy_connections(&content)? {
+            config.save()?;
+            println!("Migrated connections in {FILE_NAME} to the new format");
+        }
         Ok(config)
     }
 
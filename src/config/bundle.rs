@@ -1,10 +1,18 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 pub const CONFIG_NAME: &str = "lovebrew.toml";
+/// Directory holding the LÖVE Potion runtimes packaged builds are fused onto
+pub const DEFAULT_RUNTIME: &str = "runtime";
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum PlatformTarget {
     Ctr,
@@ -24,12 +32,26 @@ impl FromStr for PlatformTarget {
     }
 }
 
+impl fmt::Display for PlatformTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Ctr => "ctr",
+            Self::Hac => "hac",
+            Self::Cafe => "cafe",
+        };
+        f.write_str(name)
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct Metadata {
     pub name: String,
     pub author: String,
     pub description: String,
     pub version: String,
+    /// High resolution image converted for targets without an icon of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
     pub icons: HashMap<PlatformTarget, String>,
 }
 
@@ -39,11 +61,69 @@ impl Metadata {
     }
 }
 
+/// The methods LÖVE Potion can read bundle entries with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionMethod {
+    Store,
+    Deflate,
+}
+
+/// A rule is either a bare method or a table with a level
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CompressionEntry {
+    Method(CompressionMethod),
+    Table {
+        method: CompressionMethod,
+        level: Option<i64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CompressionEntry")]
+pub struct Compression {
+    pub method: CompressionMethod,
+    /// 0 to 9 for deflate, the default level otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<i64>,
+}
+
+impl From<CompressionEntry> for Compression {
+    fn from(entry: CompressionEntry) -> Self {
+        match entry {
+            CompressionEntry::Method(method) => Self {
+                method,
+                level: None,
+            },
+            CompressionEntry::Table { method, level } => Self { method, level },
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct Build {
     pub targets: Vec<PlatformTarget>,
     pub source: String,
     pub packaged: bool,
+    #[serde(default = "default_runtime")]
+    pub runtime: String,
+    /// Also leave out whatever the project's `.gitignore` files ignore
+    #[serde(default)]
+    pub gitignore: bool,
+    /// Precompile Lua to bytecode for the target's runtime
+    #[serde(default)]
+    pub compile_lua: bool,
+    /// Strip comments and whitespace from Lua instead of compiling it
+    #[serde(default)]
+    pub minify_lua: bool,
+    /// Compression by glob (`"levels/*.lua"`) or extension (`"ogg"`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub compression: BTreeMap<String, Compression>,
+}
+
+fn default_runtime() -> String {
+    String::from(DEFAULT_RUNTIME)
 }
 
 impl Build {
@@ -57,3 +137,25 @@ pub struct BundleConfig {
     pub metadata: Metadata,
     pub build: Build,
 }
+
+impl BundleConfig {
+    pub fn exists() -> bool {
+        Path::new(CONFIG_NAME).exists()
+    }
+
+    pub fn load() -> Result<Self> {
+        if !Self::exists() {
+            let cwd = std::env::current_dir()?;
+            anyhow::bail!("Could not find `{CONFIG_NAME}` in `{}`", cwd.display());
+        }
+
+        let contents = std::fs::read_to_string(CONFIG_NAME)?;
+        let config = toml::from_str::<BundleConfig>(&contents)?;
+        if config.build.compile_lua && config.build.minify_lua {
+            anyhow::bail!(
+                "`build.compile_lua` and `build.minify_lua` can't both be set in `{CONFIG_NAME}`, pick one"
+            );
+        }
+        Ok(config)
+    }
+}
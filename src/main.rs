@@ -7,10 +7,12 @@ mod services;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use commands::{bundle::BundleCmd, conn::ConfigCmd, debug::DebugCmd};
+use commands::{bundle::BundleCmd, conn::ConfigCmd, debug::DebugCmd, send::SendCmd};
 use config::app::Config;
 
-use commands::{bundle::handle_bundle, conn::handle_connection, debug::handle_debug};
+use commands::{
+    bundle::handle_bundle, conn::handle_connection, debug::handle_debug, send::handle_send,
+};
 
 #[derive(Parser)]
 #[command(author="support@lovebrew.org", version, about, long_about = None)]
@@ -40,6 +42,9 @@ enum Commands {
         #[command(subcommand)]
         command: BundleCmd,
     },
+    /// Send a build to a target device and launch it
+    #[command(alias = "s")]
+    Send(SendCmd),
 }
 
 fn main() -> Result<()> {
@@ -49,6 +54,7 @@ fn main() -> Result<()> {
     match cli.command {
         Commands::Config { command } => handle_connection(command, config),
         Commands::Debug { command } => handle_debug(command, config),
-        Commands::Bundle { command } => handle_bundle(command),
+        Commands::Bundle { command } => handle_bundle(command, config),
+        Commands::Send(command) => handle_send(command, config),
     }
 }
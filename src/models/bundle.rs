@@ -1,117 +1,904 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
+use std::io::{Cursor, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use anyhow::Result;
-use walkdir::WalkDir;
-use zip::ZipWriter;
+use anyhow::{Result, bail};
+use chrono::{Datelike, Timelike};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use mlua::Lua;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use walkdir::{DirEntry, WalkDir};
 use zip::write::SimpleFileOptions;
+use zip::{DateTime, ZipArchive, ZipWriter};
 
-use crate::config::bundle::{BundleConfig, CONFIG_NAME};
+use crate::config::bundle::{BundleConfig, CONFIG_NAME, PlatformTarget};
+use crate::models::compression::Compressor;
+use crate::models::ignore::{IGNORE_NAME, IgnoreRules, Reason};
+use crate::models::manifest::{Manifest, Record};
+use crate::models::minify::minify;
+use crate::platforms::bytecode;
+use crate::platforms::texture::{self, Conversion};
 
-const BUNDLE_NAME: &str = "bundle.zip";
-const IGNORE_NAME: &str = ".bundleignore";
+pub const BUNDLE_NAME: &str = "game.zip";
+const MAIN_NAME: &str = "main.lua";
+/// Where the lines of minified Lua came from, written next to the bundle
+pub const SOURCE_MAP_NAME: &str = "sourcemap.toml";
+/// Bundles are written here by default, so it is never bundled itself
+pub const OUTPUT_DIR: &str = "build";
+/// Textures and fonts converted for the 3DS are kept here between builds
+const TEXTURE_CACHE: &str = ".textures";
+/// The manifests of the last build of each target are kept here
+const CACHE_DIR: &str = ".cache";
+/// Overrides the time entries are stamped with, see
+/// https://reproducible-builds.org/specs/source-date-epoch/
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+/// zip stores times from 1980-01-01 to 2107-12-31 23:59:58, in seconds
+/// since the epoch
+const ZIP_TIMES: std::ops::RangeInclusive<i64> = 315_532_800..=4_354_819_198;
+/// How many entries are processed at once, only their compressed data is
+/// held in memory before being written
+const CHUNK_SIZE: usize = 64;
+const FILE_MODE: u32 = 0o644;
+const DIRECTORY_MODE: u32 = 0o755;
 
-const IGNORE_DATA: &[&str; 7] = &[
+const IGNORE_DATA: &[&str; 6] = &[
     ".git",
     ".gitignore",
     ".gitattributes",
     ".gitmodules",
     ".hg",
     ".svn",
-    "bundle.zip",
 ];
 
-pub struct Bundle {
+/// A file or directory going into a bundle.
+pub struct Entry {
+    pub path: PathBuf,
+    pub zip_path: PathBuf,
+    pub is_dir: bool,
+    pub conversion: Option<Conversion>,
+}
+
+impl Entry {
+    /// The path in the archive, with forward slashes
+    pub fn name(&self) -> String {
+        self.zip_path.to_string_lossy().replace('\\', "/")
+    }
+}
+
+/// A path left out of a bundle, relative to the project.
+pub struct Excluded {
+    pub path: PathBuf,
+    pub reason: Reason,
+}
+
+/// Everything a bundle would be made of.
+#[derive(Default)]
+pub struct Listing {
+    pub included: Vec<Entry>,
+    pub excluded: Vec<Excluded>,
+}
+
+/// Line maps of minified Lua, by line of the output
+type Lines = Option<Vec<usize>>;
+
+/// A file processed for the archive.
+struct Prepared {
+    /// An archive holding just the compressed file, none to copy the entry
+    /// of the previous archive
+    data: Option<Vec<u8>>,
+    record: Option<Record>,
+    lines: Lines,
+}
+
+/// What the previous incremental build left behind.
+#[derive(Default)]
+struct Previous {
+    manifest: Manifest,
+    /// Whether it was built with the same settings
+    reusable: bool,
+    /// The entries of the previous archive
+    names: HashSet<String>,
+    lines: BTreeMap<String, Vec<usize>>,
+}
+
+/// How a bundle is written.
+#[derive(Default, Clone, Copy)]
+pub struct WriteOptions {
+    /// Reuse the entries of files unchanged since the last incremental build
+    pub incremental: bool,
+    /// Favour speed over size, ignoring the compression rules
+    pub fast: bool,
+}
+
+/// The archive of a game for a single target.
+pub struct Bundle<'a> {
     cwd: PathBuf,
-    config: BundleConfig,
-    zip: ZipWriter<File>,
-    options: SimpleFileOptions,
-    root_includes: Vec<PathBuf>,
+    config: &'a BundleConfig,
+    platform: PlatformTarget,
+    icon: Option<&'a Path>,
+    out_dir: PathBuf,
+    /// The modification time of every entry
+    timestamp: DateTime,
 }
 
-impl Bundle {
-    pub fn new(config: BundleConfig) -> Result<Self> {
-        let cwd = std::env::current_dir()?;
+/// The last component of a configured path, whichever separator it uses, so
+/// it can't name an entry outside of the archive
+fn file_name(path: &str) -> Option<&str> {
+    match path.rsplit(['/', '\\', ':']).next()? {
+        "" | "." | ".." => None,
+        name => Some(name),
+    }
+}
 
-        if Path::new(BUNDLE_NAME).exists() {
-            std::fs::remove_file(BUNDLE_NAME)?;
-        }
+/// Feeds whatever is written to it to the hash, sha2 has no `io::Write`
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.update(data);
+        Ok(data.len())
+    }
 
-        let file = File::create(BUNDLE_NAME)?;
-        let zip = ZipWriter::new(file);
-        let options = SimpleFileOptions::default();
-        let root_includes = Vec::new();
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The modification time of entries, `SOURCE_DATE_EPOCH` when set, so the
+/// same tree always gives the same archive
+fn timestamp() -> Result<DateTime> {
+    match std::env::var(SOURCE_DATE_EPOCH) {
+        Ok(epoch) => epoch_timestamp(&epoch),
+        Err(_) => Ok(DateTime::default()),
+    }
+}
+
+/// `epoch` in seconds as a zip time, clamped to the times zip can store
+fn epoch_timestamp(epoch: &str) -> Result<DateTime> {
+    let Ok(seconds) = epoch.trim().parse::<i64>() else {
+        bail!("{SOURCE_DATE_EPOCH} must be a number of seconds since the epoch, found `{epoch}`");
+    };
+
+    let seconds = seconds.clamp(*ZIP_TIMES.start(), *ZIP_TIMES.end());
+    let Some(date) = chrono::DateTime::from_timestamp(seconds, 0) else {
+        return Ok(DateTime::default());
+    };
+    let timestamp = DateTime::from_date_and_time(
+        date.year() as u16,
+        date.month() as u8,
+        date.day() as u8,
+        date.hour() as u8,
+        date.minute() as u8,
+        date.second() as u8,
+    );
+    Ok(timestamp.unwrap_or_default())
+}
+
+impl<'a> Bundle<'a> {
+    /// A bundle for `platform`, `icon` being its validated icon
+    pub fn new(
+        config: &'a BundleConfig,
+        platform: PlatformTarget,
+        icon: Option<&'a Path>,
+        out_dir: &Path,
+    ) -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        let out_dir = cwd.join(out_dir);
 
         Ok(Self {
             cwd,
             config,
-            zip,
-            options,
-            root_includes,
+            platform,
+            icon,
+            out_dir,
+            timestamp: timestamp()?,
         })
     }
 
-    fn collect_root_includes(&mut self) {
-        let config = &self.config;
+    /// Where the bundle is written, `<out_dir>/<platform>/game.zip`
+    pub fn path(&self) -> PathBuf {
+        self.out_dir
+            .join(self.platform.to_string())
+            .join(BUNDLE_NAME)
+    }
+
+    /// Icons of the other targets, and the image they may be converted from
+    fn other_icons(&self) -> Vec<PathBuf> {
+        let metadata = &self.config.metadata;
+        let icons = metadata
+            .icons
+            .iter()
+            .filter(|(platform, _)| **platform != self.platform)
+            .map(|(_, path)| path);
+
+        icons
+            .chain(&metadata.icon)
+            .map(|path| self.cwd.join(path))
+            .filter(|path| self.icon.is_none_or(|icon| self.cwd.join(icon) != *path))
+            .collect()
+    }
 
-        let mut includes = Vec::new();
-        includes.push(self.cwd.join(CONFIG_NAME));
+    /// Icons are stored at the root of the archive under their file name, and
+    /// icons converted from `metadata.icon` are named after the platform format
+    fn icon_entry(&self, icon: &Path) -> Entry {
+        let configured = self.config.metadata.icons.get(&self.platform);
+        let zip_path = match configured.and_then(|path| file_name(path)) {
+            Some(name) => PathBuf::from(name),
+            None => match icon.extension() {
+                Some(extension) => Path::new("icon").with_extension(extension),
+                None => PathBuf::from("icon"),
+            },
+        };
 
-        if Path::new(&self.cwd).join(IGNORE_NAME).exists() {
-            includes.push(self.cwd.join(IGNORE_NAME));
+        Entry {
+            path: icon.to_path_buf(),
+            zip_path,
+            is_dir: false,
+            conversion: None,
         }
+    }
 
-        for path in config.metadata.icons.values() {
-            if Path::new(&self.cwd).join(path).exists() {
-                includes.push(self.cwd.join(path));
-            }
+    /// LÖVE Potion on the 3DS loads converted textures and fonts in place of
+    /// the originals
+    fn conversion(&self, path: &Path) -> Option<Conversion> {
+        match self.platform {
+            PlatformTarget::Ctr => Conversion::for_path(path),
+            _ => None,
         }
+    }
 
-        includes.sort();
-        includes.dedup();
-        self.root_includes = includes;
+    /// The game's source directory, which becomes the root of the archive
+    fn source(&self) -> Result<PathBuf> {
+        let source = self.cwd.join(&self.config.build.source);
+        if !source.is_dir() {
+            bail!("Source directory `{}` not found", self.config.build.source);
+        }
+        if !source.join(MAIN_NAME).is_file() {
+            bail!(
+                "`{MAIN_NAME}` not found in `{}`, LÖVE Potion runs it from the root of the game",
+                self.config.build.source
+            );
+        }
+        Ok(source)
     }
 
-    pub fn add_tree(&mut self) -> Result<()> {
-        self.collect_root_includes();
+    fn exclusion(&self, entry: &DirEntry, rules: &mut IgnoreRules) -> Result<Option<Reason>> {
+        let path = entry.path();
+        if path == self.out_dir || path == self.cwd.join(OUTPUT_DIR) {
+            return Ok(Some(Reason::Output));
+        }
+        if path == self.cwd.join(&self.config.build.runtime) {
+            return Ok(Some(Reason::Runtime));
+        }
+        if self.other_icons().iter().any(|icon| icon == path) {
+            return Ok(Some(Reason::Icon));
+        }
+        if let Some(name) = entry.file_name().to_str()
+            && IGNORE_DATA.contains(&name)
+        {
+            return Ok(Some(Reason::Default));
+        }
+        if entry.file_name() == IGNORE_NAME {
+            return Ok(Some(Reason::Default));
+        }
+        rules.check(path, entry.file_type().is_dir())
+    }
 
+    /// Walks the source directory, recording what goes into the bundle and
+    /// what is left out and why
+    pub fn list(&self) -> Result<Listing> {
+        let source = self.source()?;
         let cwd = &self.cwd;
-        let game_dir = Path::new(&self.config.build.source);
+        let config_path = cwd.join(CONFIG_NAME);
 
-        let walker = WalkDir::new(cwd).into_iter().filter_entry(|entry| {
-            if let Some(name) = entry.file_name().to_str() {
-                return !IGNORE_DATA.contains(&name);
-            }
-            true
+        let mut listing = Listing::default();
+        listing.included.push(Entry {
+            path: config_path.clone(),
+            zip_path: PathBuf::from(CONFIG_NAME),
+            is_dir: false,
+            conversion: None,
         });
+        if let Some(icon) = self.icon {
+            listing.included.push(self.icon_entry(icon));
+        }
+
+        let mut rules = IgnoreRules::new(cwd, self.config.build.gitignore);
+        let mut walker = WalkDir::new(&source)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter();
+        while let Some(entry) = walker.next() {
+            let Ok(entry) = entry else {
+                continue;
+            };
 
-        for entry in walker.filter_map(|entry| entry.ok()) {
-            let zip_path = entry.path().strip_prefix(cwd)?;
-            if zip_path.as_os_str().is_empty() {
+            // the config and icon are added under their own names above
+            if entry.path() == config_path
+                || self.icon.is_some_and(|icon| cwd.join(icon) == entry.path())
+            {
+                continue;
+            }
+            if let Some(reason) = self.exclusion(&entry, &mut rules)? {
+                if entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                listing.excluded.push(Excluded {
+                    path: entry.path().strip_prefix(cwd)?.to_path_buf(),
+                    reason,
+                });
                 continue;
             }
 
-            let is_root_file = self.root_includes.contains(&entry.path().into());
-            let file_path = if is_root_file {
-                zip_path.to_owned()
-            } else {
-                game_dir.join(zip_path)
+            let zip_path = entry.path().strip_prefix(&source)?;
+            let is_dir = !entry.path().is_file();
+            let conversion = match is_dir {
+                true => None,
+                false => self.conversion(entry.path()),
+            };
+            let zip_path = match conversion {
+                Some(conversion) => zip_path.with_extension(conversion.extension()),
+                None => zip_path.to_path_buf(),
             };
 
-            if entry.path().is_file() {
-                let mut file = File::open(entry.path())?;
-                self.zip.start_file_from_path(file_path, self.options)?;
-                std::io::copy(&mut file, &mut self.zip)?;
-            } else {
-                self.zip.add_directory_from_path(file_path, self.options)?;
+            listing.included.push(Entry {
+                path: entry.path().to_path_buf(),
+                zip_path,
+                is_dir,
+                conversion,
+            });
+        }
+        Ok(listing)
+    }
+
+    /// Precompiles or minifies a Lua file as configured, along with where the
+    /// lines of minified files came from
+    fn process_lua(&self, lua: &Lua, entry: &Entry) -> Result<Option<(Vec<u8>, Lines)>> {
+        let build = &self.config.build;
+        let is_lua = entry.zip_path.extension().is_some_and(|ext| ext == "lua");
+        if !is_lua || !(build.compile_lua || build.minify_lua) {
+            return Ok(None);
+        }
+
+        let source = std::fs::read(&entry.path)?;
+        if build.compile_lua {
+            let chunkname = format!("@{}", entry.name());
+            return match bytecode::compile(lua, &source, &chunkname, self.platform) {
+                Ok(bytecode) => Ok(Some((bytecode, None))),
+                Err(e) => bail!("Failed to compile {}: {e}", entry.path.display()),
+            };
+        }
+
+        let minified = minify(&String::from_utf8_lossy(&source));
+        Ok(Some((minified.source.into_bytes(), Some(minified.lines))))
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.out_dir
+            .join(CACHE_DIR)
+            .join(format!("{}.toml", self.platform))
+    }
+
+    /// Settings that change how entries are processed, changing them
+    /// rebuilds every entry
+    fn settings(&self, options: WriteOptions) -> String {
+        let build = &self.config.build;
+        format!(
+            "compile_lua = {}, minify_lua = {}, compression = {:?}, fast = {}, timestamp = {}",
+            build.compile_lua, build.minify_lua, build.compression, options.fast, self.timestamp
+        )
+    }
+
+    /// The line maps written with the previous bundle
+    fn previous_lines(&self) -> BTreeMap<String, Vec<usize>> {
+        let path = self.path().with_file_name(SOURCE_MAP_NAME);
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Prints what was added, changed and removed since the previous build
+    fn report(previous: &Manifest, manifest: &Manifest, reused: usize) {
+        if previous.entries.is_empty() {
+            println!(
+                "No previous build to reuse, wrote {} files",
+                manifest.entries.len()
+            );
+            return;
+        }
+
+        let (mut added, mut changed, mut removed) = (0, 0, 0);
+        for (name, record) in &manifest.entries {
+            match previous.entries.get(name) {
+                None => {
+                    println!("  + {name}");
+                    added += 1;
+                }
+                Some(old) if old.hash != record.hash => {
+                    println!("  ~ {name}");
+                    changed += 1;
+                }
+                Some(_) => {}
+            }
+        }
+        for name in previous.entries.keys() {
+            if !manifest.entries.contains_key(name) {
+                println!("  - {name}");
+                removed += 1;
             }
         }
+        println!(
+            "{added} added, {changed} changed, {removed} removed, {reused} of {} reused",
+            manifest.entries.len()
+        );
+    }
+
+    /// The source map goes with the bundle, stale ones are removed
+    fn write_source_map(&self, lines: &BTreeMap<String, Vec<usize>>) -> Result<()> {
+        let path = self.path().with_file_name(SOURCE_MAP_NAME);
+        if !lines.is_empty() {
+            std::fs::write(path, toml::to_string(lines)?)?;
+        } else if path.exists() {
+            std::fs::remove_file(path)?;
+        }
         Ok(())
     }
 
-    pub fn finish(self) -> Result<()> {
-        self.zip.finish()?;
-        println!("{BUNDLE_NAME} created successfully.");
+    /// Processes and compresses a file on its own, into an archive of one entry
+    fn compress(&self, entry: &Entry, lua: &Lua, compressor: &Compressor) -> Result<Prepared> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = compressor
+            .options(&entry.zip_path)
+            .last_modified_time(self.timestamp)
+            .unix_permissions(FILE_MODE);
+        zip.start_file(entry.name(), options)?;
+
+        let lines = match self.process_lua(lua, entry)? {
+            Some((data, lines)) => {
+                zip.write_all(&data)?;
+                lines
+            }
+            None => {
+                let path = match entry.conversion {
+                    Some(conversion) => {
+                        let cache = self.out_dir.join(TEXTURE_CACHE);
+                        texture::convert(conversion, &entry.path, &cache)?
+                    }
+                    None => entry.path.clone(),
+                };
+                std::io::copy(&mut File::open(path)?, &mut zip)?;
+                None
+            }
+        };
+
+        Ok(Prepared {
+            data: Some(zip.finish()?.into_inner()),
+            record: None,
+            lines,
+        })
+    }
+
+    /// Reads, converts and compresses a file, unless the previous archive
+    /// has it as it is
+    fn prepare(
+        &self,
+        entry: &Entry,
+        lua: &Lua,
+        compressor: &Compressor,
+        previous: Option<&Previous>,
+    ) -> Result<Prepared> {
+        let Some(previous) = previous else {
+            return self.compress(entry, lua, compressor);
+        };
+
+        let name = entry.name();
+        let old = previous
+            .manifest
+            .entries
+            .get(&name)
+            .filter(|_| previous.reusable);
+        let record = Record::read(&entry.path, old)?;
+        let unchanged = old.is_some_and(|old| old.hash == record.hash);
+
+        let mut prepared = match unchanged && previous.names.contains(&name) {
+            true => Prepared {
+                data: None,
+                record: None,
+                lines: previous.lines.get(&name).cloned(),
+            },
+            false => self.compress(entry, lua, compressor)?,
+        };
+        prepared.record = Some(record);
+        Ok(prepared)
+    }
+
+    /// The hash of the archive, the same for the same tree and settings
+    fn digest(path: &Path) -> Result<String> {
+        let mut hasher = HashWriter(Sha256::new());
+        std::io::copy(&mut File::open(path)?, &mut hasher)?;
+        let digest = hasher.0.finalize();
+        Ok(digest.iter().map(|b| format!("{b:02x}")).collect())
+    }
+
+    /// Prints how much compression saved for each type of file in the archive
+    fn print_savings(path: &Path) -> Result<()> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut savings: BTreeMap<String, (usize, u64, u64)> = BTreeMap::new();
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index)?;
+            if file.is_dir() {
+                continue;
+            }
+            let category = match Path::new(file.name()).extension() {
+                Some(extension) => format!(".{}", extension.to_string_lossy().to_lowercase()),
+                None => String::from("other"),
+            };
+            let (files, size, compressed) = savings.entry(category).or_default();
+            *files += 1;
+            *size += file.size();
+            *compressed += file.compressed_size();
+        }
+
+        println!(
+            "{:<10} {:>5} {:>11} {:>11} {:>11}",
+            "Type", "Files", "Size", "Compressed", "Saved"
+        );
+        for (category, (files, size, compressed)) in savings {
+            let saved = size.saturating_sub(compressed);
+            let percent = match size {
+                0 => 0.0,
+                size => 100.0 * saved as f64 / size as f64,
+            };
+            println!(
+                "{category:<10} {files:>5} {:>11} {:>11} {:>11} {percent:>5.1}%",
+                HumanBytes(size).to_string(),
+                HumanBytes(compressed).to_string(),
+                HumanBytes(saved).to_string()
+            );
+        }
         Ok(())
     }
+
+    /// What the previous incremental build left behind, and its archive
+    fn previous(&self, settings: &str) -> (Previous, Option<ZipArchive<File>>) {
+        let archive = File::open(self.path())
+            .ok()
+            .and_then(|file| ZipArchive::new(file).ok());
+        let Some(archive) = archive else {
+            return (Previous::default(), None);
+        };
+
+        let manifest = Manifest::load(&self.manifest_path());
+        let previous = Previous {
+            reusable: manifest.settings == settings,
+            names: archive.file_names().map(String::from).collect(),
+            lines: self.previous_lines(),
+            manifest,
+        };
+        (previous, Some(archive))
+    }
+
+    fn create_progress(&self, files: &[&Entry]) -> Result<ProgressBar> {
+        let size = files
+            .iter()
+            .map(|entry| entry.path.metadata().map_or(0, |metadata| metadata.len()))
+            .sum();
+        let progress_bar = ProgressBar::new(size);
+        let template = ProgressStyle::with_template("{msg} [{bar:40}] {bytes}/{total_bytes}")?
+            .progress_chars("=> ");
+        progress_bar.set_style(template);
+        progress_bar.set_message(format!(
+            "Bundling {}: 0/{} files",
+            self.platform,
+            files.len()
+        ));
+        Ok(progress_bar)
+    }
+
+    /// Writes the entries of `listing` to the archive, converting as needed.
+    ///
+    /// Files are processed in parallel, a chunk at a time, and written in the
+    /// order of `listing`.
+    /// When incremental, entries whose files are unchanged since the last
+    /// incremental build are copied from the previous archive as they are.
+    pub fn write(&self, listing: &Listing, options: WriteOptions) -> Result<PathBuf> {
+        let path = self.path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let settings = self.settings(options);
+        let (previous, mut previous_zip) = match options.incremental {
+            true => {
+                let (previous, archive) = self.previous(&settings);
+                (Some(previous), archive)
+            }
+            false => (None, None),
+        };
+
+        let files: Vec<&Entry> = listing
+            .included
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .collect();
+        let compressor = Compressor::new(&self.config.build.compression, options.fast)?;
+        let progress = self.create_progress(&files)?;
+        let done = AtomicUsize::new(0);
+
+        // the previous archive is read from until the new one is complete
+        let scratch = path.with_extension("zip.part");
+        let mut zip = ZipWriter::new(File::create(&scratch)?);
+        let mut manifest = Manifest {
+            settings,
+            entries: BTreeMap::new(),
+        };
+        let mut lines = BTreeMap::new();
+        let mut reused = 0;
+
+        for chunk in listing.included.chunks(CHUNK_SIZE) {
+            let prepared: Vec<Prepared> = chunk
+                .par_iter()
+                .filter(|entry| !entry.is_dir)
+                .map_init(Lua::new, |lua, entry| {
+                    let prepared = self.prepare(entry, lua, &compressor, previous.as_ref())?;
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress.inc(entry.path.metadata().map_or(0, |metadata| metadata.len()));
+                    progress.set_message(format!(
+                        "Bundling {}: {done}/{} files",
+                        self.platform,
+                        files.len()
+                    ));
+                    Ok(prepared)
+                })
+                .collect::<Result<_>>()?;
+
+            let mut prepared = prepared.into_iter();
+            for entry in chunk {
+                if entry.is_dir {
+                    let options = SimpleFileOptions::default()
+                        .last_modified_time(self.timestamp)
+                        .unix_permissions(DIRECTORY_MODE);
+                    zip.add_directory(entry.name(), options)?;
+                    continue;
+                }
+                let Some(file) = prepared.next() else {
+                    bail!("{} was not processed", entry.path.display());
+                };
+
+                let name = entry.name();
+                let previous_index = previous_zip.as_mut().and_then(|previous_zip| {
+                    Some((previous_zip.index_for_name(&name)?, previous_zip))
+                });
+                match (file.data, previous_index) {
+                    (Some(data), _) => {
+                        let mut archive = ZipArchive::new(Cursor::new(data))?;
+                        zip.raw_copy_file(archive.by_index_raw(0)?)?;
+                    }
+                    (None, Some((index, previous_zip))) => {
+                        zip.raw_copy_file(previous_zip.by_index_raw(index)?)?;
+                        reused += 1;
+                    }
+                    (None, None) => bail!("`{name}` is missing from the previous bundle"),
+                }
+                if let Some(record) = file.record {
+                    manifest.entries.insert(name.clone(), record);
+                }
+                if let Some(file_lines) = file.lines {
+                    lines.insert(name, file_lines);
+                }
+            }
+        }
+        progress.finish_and_clear();
+
+        zip.finish()?;
+        drop(previous_zip);
+        std::fs::rename(&scratch, &path)?;
+        self.write_source_map(&lines)?;
+
+        let manifest_path = self.manifest_path();
+        match &previous {
+            Some(previous) => {
+                Self::report(&previous.manifest, &manifest, reused);
+                manifest.save(&manifest_path)?;
+            }
+            // the archive no longer matches it
+            None if manifest_path.exists() => std::fs::remove_file(&manifest_path)?,
+            None => {}
+        }
+        Self::print_savings(&path)?;
+        println!("{} created successfully.", path.display());
+        println!("SHA-256: {}", Self::digest(&path)?);
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::bundle::{Build, Metadata};
+
+    use super::*;
+
+    const INCREMENTAL: WriteOptions = WriteOptions {
+        incremental: true,
+        fast: false,
+    };
+
+    /// A project with a `main.lua` and an asset, removed on drop
+    struct Project {
+        root: PathBuf,
+        config: BundleConfig,
+    }
+
+    impl Project {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("nestcli-bundle-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(root.join("src")).unwrap();
+            std::fs::write(root.join(CONFIG_NAME), "").unwrap();
+            std::fs::write(root.join("src").join(MAIN_NAME), "print(1)").unwrap();
+            std::fs::write(root.join("src/data.txt"), "data").unwrap();
+
+            let config = BundleConfig {
+                metadata: Metadata::default(),
+                build: Build {
+                    targets: vec![PlatformTarget::Hac],
+                    source: String::from("src"),
+                    ..Build::default()
+                },
+            };
+            Self { root, config }
+        }
+
+        fn bundle(&self) -> Bundle<'_> {
+            self.bundle_with_icon(None)
+        }
+
+        fn bundle_with_icon<'a>(&'a self, icon: Option<&'a Path>) -> Bundle<'a> {
+            Bundle {
+                cwd: self.root.clone(),
+                config: &self.config,
+                platform: PlatformTarget::Hac,
+                icon,
+                out_dir: self.root.join(OUTPUT_DIR),
+                timestamp: DateTime::default(),
+            }
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    /// Which files of the listing would be compressed again
+    fn rebuilt(bundle: &Bundle, options: WriteOptions) -> Vec<String> {
+        let (previous, _) = bundle.previous(&bundle.settings(options));
+        let compressor = Compressor::new(&BTreeMap::new(), options.fast).unwrap();
+        let lua = Lua::new();
+        let listing = bundle.list().unwrap();
+        listing
+            .included
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .filter(|entry| {
+                let prepared = bundle.prepare(entry, &lua, &compressor, Some(&previous));
+                prepared.unwrap().data.is_some()
+            })
+            .map(Entry::name)
+            .collect()
+    }
+
+    #[test]
+    fn clamps_the_source_date_epoch() {
+        let timestamp = |epoch| {
+            let time = epoch_timestamp(epoch).unwrap();
+            let date = (time.year(), time.month(), time.day());
+            (date, time.hour(), time.minute(), time.second())
+        };
+
+        assert_eq!(timestamp("1700000000"), ((2023, 11, 14), 22, 13, 20));
+        assert_eq!(timestamp(" 315532800\n"), ((1980, 1, 1), 0, 0, 0));
+        assert_eq!(timestamp("315532799"), ((1980, 1, 1), 0, 0, 0));
+        assert_eq!(timestamp("-86399"), ((1980, 1, 1), 0, 0, 0));
+        assert_eq!(timestamp("99999999999"), ((2107, 12, 31), 23, 59, 58));
+        assert!(epoch_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn names_icons_outside_the_project_by_file_name() {
+        let mut project = Project::new("icon");
+        let shared = project.root.with_extension("shared");
+        std::fs::create_dir_all(&shared).unwrap();
+        let icon = shared.join("icon256.jpg");
+        std::fs::write(&icon, "jpeg").unwrap();
+
+        for (configured, expected) in [
+            ("../shared/icon256.jpg", "icon256.jpg"),
+            ("..\\shared\\icon256.jpg", "icon256.jpg"),
+            ("/abs/icon256.jpg", "icon256.jpg"),
+            ("C:icon256.jpg", "icon256.jpg"),
+            ("../..", "icon.jpg"),
+        ] {
+            let icons = &mut project.config.metadata.icons;
+            icons.insert(PlatformTarget::Hac, String::from(configured));
+            let bundle = project.bundle_with_icon(Some(&icon));
+            let listing = bundle.list().unwrap();
+            let entry = listing.included.iter().find(|entry| entry.path == icon);
+            assert_eq!(entry.unwrap().name(), expected, "{configured}");
+        }
+        std::fs::remove_dir_all(shared).unwrap();
+    }
+
+    #[test]
+    fn reuses_unchanged_files() {
+        let project = Project::new("unchanged");
+        let bundle = project.bundle();
+        assert_eq!(rebuilt(&bundle, INCREMENTAL).len(), 3);
+
+        bundle.write(&bundle.list().unwrap(), INCREMENTAL).unwrap();
+        assert!(rebuilt(&bundle, INCREMENTAL).is_empty());
+    }
+
+    #[test]
+    fn rebuilds_changed_files() {
+        let project = Project::new("changed");
+        let bundle = project.bundle();
+        bundle.write(&bundle.list().unwrap(), INCREMENTAL).unwrap();
+
+        std::fs::write(project.root.join("src/data.txt"), "new data").unwrap();
+        assert_eq!(rebuilt(&bundle, INCREMENTAL), ["data.txt"]);
+    }
+
+    #[test]
+    fn rebuilds_everything_when_settings_change() {
+        let project = Project::new("settings");
+        let bundle = project.bundle();
+        bundle.write(&bundle.list().unwrap(), INCREMENTAL).unwrap();
+
+        let fast = WriteOptions {
+            fast: true,
+            ..INCREMENTAL
+        };
+        assert_eq!(rebuilt(&bundle, fast).len(), 3);
+    }
+
+    #[test]
+    fn writes_entries_in_listing_order_across_chunks() {
+        let project = Project::new("order");
+        for index in 0..CHUNK_SIZE * 2 {
+            let path = project.root.join(format!("src/levels/{index:03}.txt"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, index.to_string()).unwrap();
+        }
+        let bundle = project.bundle();
+        let listing = bundle.list().unwrap();
+        let path = bundle.write(&listing, INCREMENTAL).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let names: Vec<String> = listing.included.iter().map(Entry::name).collect();
+        let written: Vec<String> = (0..archive.len())
+            .map(|index| archive.by_index_raw(index).unwrap().name().to_string())
+            .collect();
+        assert_eq!(written.len(), names.len());
+        for (written, name) in written.iter().zip(&names) {
+            assert_eq!(written.trim_end_matches('/'), name.trim_end_matches('/'));
+        }
+    }
+
+    #[test]
+    fn keeps_no_manifest_for_full_builds() {
+        let project = Project::new("full");
+        let bundle = project.bundle();
+        bundle.write(&bundle.list().unwrap(), INCREMENTAL).unwrap();
+        assert!(bundle.manifest_path().is_file());
+
+        bundle
+            .write(&bundle.list().unwrap(), WriteOptions::default())
+            .unwrap();
+        assert!(!bundle.manifest_path().exists());
+    }
 }
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::texture::Conversion;
+
+const ALL_TARGETS: &[PlatformTarget] = &[
+    PlatformTarget::Ctr,
+    PlatformTarget::Hac,
+    PlatformTarget::Cafe,
+];
+
+/// LÖVE APIs LÖVE Potion doesn't implement, and the targets they are missing on
+const UNSUPPORTED_APIS: &[(&str, &[PlatformTarget])] = &[
+    ("love.physics", ALL_TARGETS),
+    ("love.video", ALL_TARGETS),
+    ("love.mouse", ALL_TARGETS),
+    ("love.graphics.newShader", ALL_TARGETS),
+    ("love.graphics.setShader", ALL_TARGETS),
+    ("love.graphics.newVideo", ALL_TARGETS),
+    ("love.graphics.newMesh", &[PlatformTarget::Ctr]),
+    (
+        "love.system.openURL",
+        &[PlatformTarget::Ctr, PlatformTarget::Cafe],
+    ),
+];
+
+/// Asset formats LÖVE Potion can't load, and the targets that can't
+const UNSUPPORTED_ASSETS: &[(&str, &[PlatformTarget])] = &[
+    ("mp3", &[PlatformTarget::Ctr]),
+    ("bmp", ALL_TARGETS),
+    ("tga", ALL_TARGETS),
+    ("gif", ALL_TARGETS),
+    ("dds", ALL_TARGETS),
+    ("ogv", ALL_TARGETS),
+    ("glsl", ALL_TARGETS),
+    ("frag", ALL_TARGETS),
+    ("vert", ALL_TARGETS),
+    ("xm", ALL_TARGETS),
+    ("mod", ALL_TARGETS),
+    ("it", ALL_TARGETS),
+    ("s3m", ALL_TARGETS),
+];
+
+/// Asset formats LÖVE Potion loads as-is, besides the converted ones
+const SUPPORTED_ASSETS: &[&str] = &[
+    "png", "jpg", "jpeg", "ttf", "otf", "ogg", "wav", "flac", "mp3",
+];
+
+/// Whether a feature works on a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    Yes,
+    /// Converted to the target's own format when bundling
+    Converted,
+    No,
+}
+
+impl fmt::Display for Support {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yes => f.write_str("yes"),
+            Self::Converted => f.write_str("converted"),
+            Self::No => f.write_str("no"),
+        }
+    }
+}
+
+/// The unsupported API `api` belongs to, and the targets missing it
+pub fn unsupported_api(api: &str) -> Option<(&'static str, &'static [PlatformTarget])> {
+    UNSUPPORTED_APIS
+        .iter()
+        .find(|(prefix, _)| api == *prefix || api.starts_with(&format!("{prefix}.")))
+        .copied()
+}
+
+pub fn api_support(api: &str, platform: PlatformTarget) -> Support {
+    match unsupported_api(api) {
+        Some((_, missing)) if missing.contains(&platform) => Support::No,
+        _ => Support::Yes,
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_lowercase())
+}
+
+/// How `platform` handles the asset at `path`, if it's a known asset format
+pub fn asset_support(path: &Path, platform: PlatformTarget) -> Option<Support> {
+    let extension = extension(path)?;
+    if let Some((_, missing)) = UNSUPPORTED_ASSETS.iter().find(|(ext, _)| *ext == extension) {
+        if missing.contains(&platform) {
+            return Some(Support::No);
+        }
+    } else if !SUPPORTED_ASSETS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    match (platform, Conversion::for_path(path)) {
+        (PlatformTarget::Ctr, Some(_)) => Some(Support::Converted),
+        _ => Some(Support::Yes),
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Feature {
+    Api(String),
+    /// An asset format, by extension
+    Asset(String),
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Api(api) => f.write_str(api),
+            Self::Asset(extension) => write!(f, ".{extension} files"),
+        }
+    }
+}
+
+/// The LÖVE APIs and asset formats a game uses, and where.
+#[derive(Default)]
+pub struct Report {
+    features: BTreeMap<Feature, BTreeSet<PathBuf>>,
+    /// An asset of each format, to tell how the targets handle it
+    samples: BTreeMap<String, PathBuf>,
+}
+
+impl Report {
+    pub fn add_api(&mut self, api: &str, file: &Path) {
+        let feature = Feature::Api(api.to_string());
+        self.features
+            .entry(feature)
+            .or_default()
+            .insert(file.to_path_buf());
+    }
+
+    /// Records `path` if it's in one of the asset formats we know about
+    pub fn add_asset(&mut self, path: &Path) {
+        let Some(extension) = extension(path) else {
+            return;
+        };
+        if asset_support(path, PlatformTarget::Hac).is_none() {
+            return;
+        }
+        self.samples
+            .entry(extension.clone())
+            .or_insert_with(|| path.to_path_buf());
+        self.features
+            .entry(Feature::Asset(extension))
+            .or_default()
+            .insert(path.to_path_buf());
+    }
+
+    fn support(&self, feature: &Feature, platform: PlatformTarget) -> Support {
+        match feature {
+            Feature::Api(api) => api_support(api, platform),
+            Feature::Asset(extension) => {
+                asset_support(&self.samples[extension], platform).unwrap_or(Support::Yes)
+            }
+        }
+    }
+
+    /// Prints a row per feature with its support on each of `targets`
+    pub fn print(&self, targets: &[PlatformTarget]) {
+        let width = self
+            .features
+            .keys()
+            .map(|feature| feature.to_string().len())
+            .max()
+            .unwrap_or_default()
+            .max("Feature".len());
+
+        let mut header = format!("{:<width$} {:<9}", "Feature", "Used in");
+        for target in targets {
+            header += &format!(" {:<9}", target.to_string());
+        }
+        println!("{}", header.trim_end());
+
+        for (feature, files) in &self.features {
+            let used = match files.len() {
+                1 => String::from("1 file"),
+                count => format!("{count} files"),
+            };
+            let mut row = format!("{:<width$} {used:<9}", feature.to_string());
+            for &target in targets {
+                row += &format!(" {:<9}", self.support(feature, target).to_string());
+            }
+            println!("{}", row.trim_end());
+        }
+
+        println!();
+        for &target in targets {
+            let unsupported = self
+                .features
+                .keys()
+                .filter(|feature| self.support(feature, target) == Support::No)
+                .count();
+            println!(
+                "{target}: {unsupported} of {} features unsupported",
+                self.features.len()
+            );
+        }
+    }
+}
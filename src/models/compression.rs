@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use globset::{Glob, GlobMatcher};
+use zip::CompressionMethod as ZipMethod;
+use zip::write::SimpleFileOptions;
+
+use crate::config::bundle::{Compression, CompressionMethod};
+
+/// Formats that are compressed already, deflating them again gains nothing
+const COMPRESSED_FORMATS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "ogg", "mp3", "flac", "ogv", "t3x", "zip", "gz",
+];
+const MAX_DEFLATE_LEVEL: i64 = 9;
+/// Deflate level of `--fast` builds
+const FAST_LEVEL: i64 = 1;
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_lowercase())
+}
+
+/// Picks the compression of each entry from the `[build.compression]` rules.
+///
+/// Globs are matched against the path in the archive, longest pattern first,
+/// before rules by extension. Known compressed formats are stored by default.
+pub struct Compressor {
+    globs: Vec<(GlobMatcher, Compression)>,
+    extensions: HashMap<String, Compression>,
+    fast: bool,
+}
+
+impl Compressor {
+    /// `fast` ignores the rules, storing compressed formats and deflating
+    /// everything else at the lowest level
+    pub fn new(rules: &BTreeMap<String, Compression>, fast: bool) -> Result<Self> {
+        let mut globs = Vec::new();
+        let mut extensions = HashMap::new();
+
+        for (pattern, compression) in rules {
+            match (compression.method, compression.level) {
+                (CompressionMethod::Store, Some(_)) => {
+                    bail!("Invalid compression for `{pattern}`, store does not take a level")
+                }
+                (CompressionMethod::Deflate, Some(level))
+                    if !(0..=MAX_DEFLATE_LEVEL).contains(&level) =>
+                {
+                    bail!(
+                        "Invalid compression level {level} for `{pattern}`, deflate takes 0 to {MAX_DEFLATE_LEVEL}"
+                    )
+                }
+                _ => {}
+            }
+
+            if pattern.contains(['*', '?', '[', '{', '/']) {
+                globs.push((Glob::new(pattern)?.compile_matcher(), *compression));
+            } else {
+                let extension = pattern.trim_start_matches('.').to_lowercase();
+                extensions.insert(extension, *compression);
+            }
+        }
+        globs.sort_by_key(|(glob, _)| std::cmp::Reverse(glob.glob().glob().len()));
+
+        Ok(Self {
+            globs,
+            extensions,
+            fast,
+        })
+    }
+
+    fn default_compression(&self, zip_path: &Path) -> Compression {
+        let compressed = extension(zip_path)
+            .is_some_and(|extension| COMPRESSED_FORMATS.contains(&extension.as_str()));
+        match (compressed, self.fast) {
+            (true, _) => Compression {
+                method: CompressionMethod::Store,
+                level: None,
+            },
+            (false, fast) => Compression {
+                method: CompressionMethod::Deflate,
+                level: fast.then_some(FAST_LEVEL),
+            },
+        }
+    }
+
+    pub fn compression(&self, zip_path: &Path) -> Compression {
+        if self.fast {
+            return self.default_compression(zip_path);
+        }
+        if let Some((_, compression)) = self.globs.iter().find(|(glob, _)| glob.is_match(zip_path))
+        {
+            return *compression;
+        }
+        extension(zip_path)
+            .and_then(|extension| self.extensions.get(&extension).copied())
+            .unwrap_or_else(|| self.default_compression(zip_path))
+    }
+
+    pub fn options(&self, zip_path: &Path) -> SimpleFileOptions {
+        let compression = self.compression(zip_path);
+        let method = match compression.method {
+            CompressionMethod::Store => ZipMethod::Stored,
+            CompressionMethod::Deflate => ZipMethod::Deflated,
+        };
+        SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(compression.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(method: CompressionMethod, level: Option<i64>) -> Compression {
+        Compression { method, level }
+    }
+
+    fn error(pattern: &str, compression: Compression) -> String {
+        let rules = BTreeMap::from([(String::from(pattern), compression)]);
+        match Compressor::new(&rules, false) {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_levels() {
+        assert_eq!(
+            error("wav", rule(CompressionMethod::Store, Some(1))),
+            "Invalid compression for `wav`, store does not take a level"
+        );
+        assert_eq!(
+            error("lua", rule(CompressionMethod::Deflate, Some(10))),
+            "Invalid compression level 10 for `lua`, deflate takes 0 to 9"
+        );
+        assert!(error("lua", rule(CompressionMethod::Deflate, Some(9))).is_empty());
+    }
+
+    #[test]
+    fn prefers_longer_globs_then_extensions() {
+        let rules = BTreeMap::from([
+            (
+                String::from("lua"),
+                rule(CompressionMethod::Deflate, Some(9)),
+            ),
+            (
+                String::from("levels/*"),
+                rule(CompressionMethod::Store, None),
+            ),
+            (
+                String::from("levels/big/*.lua"),
+                rule(CompressionMethod::Deflate, Some(3)),
+            ),
+        ]);
+        let compressor = Compressor::new(&rules, false).unwrap();
+        let level = |path: &str| {
+            let compression = compressor.compression(Path::new(path));
+            (compression.method, compression.level)
+        };
+
+        assert_eq!(level("main.lua"), (CompressionMethod::Deflate, Some(9)));
+        assert_eq!(level("levels/one.lua"), (CompressionMethod::Store, None));
+        assert_eq!(
+            level("levels/big/two.lua"),
+            (CompressionMethod::Deflate, Some(3))
+        );
+        assert_eq!(level("music.ogg"), (CompressionMethod::Store, None));
+        assert_eq!(level("notes.txt"), (CompressionMethod::Deflate, None));
+
+        let fast = Compressor::new(&rules, true).unwrap();
+        assert_eq!(
+            fast.compression(Path::new("main.lua")).level,
+            Some(FAST_LEVEL)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+pub const IGNORE_NAME: &str = ".bundleignore";
+const GITIGNORE_NAME: &str = ".gitignore";
+
+/// Why a path was left out of a bundle.
+pub enum Reason {
+    Default,
+    Output,
+    Runtime,
+    Icon,
+    Pattern { file: PathBuf, pattern: String },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str("always ignored"),
+            Self::Output => f.write_str("output directory"),
+            Self::Runtime => f.write_str("runtime directory"),
+            Self::Icon => f.write_str("icon image"),
+            Self::Pattern { file, pattern } => write!(f, "{}: {pattern}", file.display()),
+        }
+    }
+}
+
+/// The `.bundleignore` files, and optionally `.gitignore` files, of a project.
+///
+/// Each directory's files apply to everything below it, with deeper files
+/// taking precedence, and `.bundleignore` winning over `.gitignore`.
+pub struct IgnoreRules {
+    root: PathBuf,
+    gitignore: bool,
+    matchers: HashMap<PathBuf, Vec<Gitignore>>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, gitignore: bool) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            gitignore,
+            matchers: HashMap::new(),
+        }
+    }
+
+    fn load(&self, directory: &Path) -> Result<Vec<Gitignore>> {
+        let mut names = vec![IGNORE_NAME];
+        if self.gitignore {
+            names.push(GITIGNORE_NAME);
+        }
+
+        let mut matchers = Vec::new();
+        for name in names {
+            let path = directory.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let mut builder = GitignoreBuilder::new(directory);
+            if let Some(e) = builder.add(&path) {
+                return Err(e.into());
+            }
+            matchers.push(builder.build()?);
+        }
+        Ok(matchers)
+    }
+
+    fn matchers(&mut self, directory: &Path) -> Result<&[Gitignore]> {
+        if !self.matchers.contains_key(directory) {
+            let matchers = self.load(directory)?;
+            self.matchers.insert(directory.to_path_buf(), matchers);
+        }
+        Ok(&self.matchers[directory])
+    }
+
+    /// The pattern excluding `path`, if any
+    pub fn check(&mut self, path: &Path, is_dir: bool) -> Result<Option<Reason>> {
+        let root = self.root.clone();
+        let directories = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&root));
+
+        for directory in directories {
+            for matcher in self.matchers(directory)? {
+                match matcher.matched(path, is_dir) {
+                    Match::None => continue,
+                    Match::Whitelist(_) => return Ok(None),
+                    Match::Ignore(glob) => {
+                        let file = glob.from().unwrap_or(Path::new(IGNORE_NAME));
+                        return Ok(Some(Reason::Pattern {
+                            file: file.strip_prefix(&root).unwrap_or(file).to_path_buf(),
+                            pattern: glob.original().to_string(),
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A project directory with the given ignore files, removed on drop
+    struct Project(PathBuf);
+
+    impl Project {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("nestcli-ignore-{}-{name}", std::process::id()));
+            for (path, content) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+            Self(root)
+        }
+
+        fn ignored(&self, rules: &mut IgnoreRules, path: &str, is_dir: bool) -> bool {
+            rules.check(&self.0.join(path), is_dir).unwrap().is_some()
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn negates_patterns() {
+        let project = Project::new("negation", &[(IGNORE_NAME, "*.txt\n!keep.txt\n")]);
+        let mut rules = IgnoreRules::new(&project.0, false);
+
+        assert!(project.ignored(&mut rules, "notes.txt", false));
+        assert!(project.ignored(&mut rules, "docs/notes.txt", false));
+        assert!(!project.ignored(&mut rules, "keep.txt", false));
+        assert!(!project.ignored(&mut rules, "docs/keep.txt", false));
+        assert!(!project.ignored(&mut rules, "main.lua", false));
+    }
+
+    #[test]
+    fn matches_directory_only_patterns_against_directories() {
+        let project = Project::new("directories", &[(IGNORE_NAME, "tmp/\n/build.lua\n")]);
+        let mut rules = IgnoreRules::new(&project.0, false);
+
+        assert!(project.ignored(&mut rules, "tmp", true));
+        assert!(project.ignored(&mut rules, "levels/tmp", true));
+        assert!(!project.ignored(&mut rules, "tmp", false));
+
+        assert!(project.ignored(&mut rules, "build.lua", false));
+        assert!(!project.ignored(&mut rules, "lib/build.lua", false));
+    }
+
+    #[test]
+    fn applies_nested_files_below_their_directory() {
+        let project = Project::new(
+            "nested",
+            &[
+                (IGNORE_NAME, "*.psd\n"),
+                ("art/.bundleignore", "!*.psd\ndraft.png\n"),
+            ],
+        );
+        let mut rules = IgnoreRules::new(&project.0, false);
+
+        assert!(project.ignored(&mut rules, "icon.psd", false));
+        assert!(!project.ignored(&mut rules, "art/icon.psd", false));
+        assert!(!project.ignored(&mut rules, "art/ui/icon.psd", false));
+        assert!(project.ignored(&mut rules, "art/draft.png", false));
+        assert!(!project.ignored(&mut rules, "draft.png", false));
+    }
+
+    #[test]
+    fn prefers_bundleignore_over_gitignore() {
+        let project = Project::new(
+            "gitignore",
+            &[(IGNORE_NAME, "!debug.log\n"), (GITIGNORE_NAME, "*.log\n")],
+        );
+
+        let mut rules = IgnoreRules::new(&project.0, false);
+        assert!(!project.ignored(&mut rules, "error.log", false));
+
+        let mut rules = IgnoreRules::new(&project.0, true);
+        assert!(project.ignored(&mut rules, "error.log", false));
+        assert!(!project.ignored(&mut rules, "debug.log", false));
+    }
+
+    #[test]
+    fn reports_the_matching_pattern() {
+        let project = Project::new("reason", &[("src/.bundleignore", "# comment\n*.bak\n")]);
+        let mut rules = IgnoreRules::new(&project.0, false);
+
+        let reason = rules.check(&project.0.join("src/a.bak"), false).unwrap();
+        let reason = reason.unwrap().to_string();
+        assert_eq!(
+            reason,
+            format!("{}: *.bak", Path::new("src/.bundleignore").display())
+        );
+    }
+}
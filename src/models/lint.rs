@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use mlua::Lua;
+use regex::Regex;
+
+use crate::config::bundle::PlatformTarget;
+use crate::models::compat::unsupported_api;
+use crate::models::minify::{blank_strings, minify, shebang_end};
+
+/// `<chunk>:<line>: <message>`, as reported by the Lua parser
+const SYNTAX_PATTERN: &str = r"^[^:]*:(\d+): (.*)$";
+const API_PATTERN: &str = r"(?:^|[^\w.])(love(?:\.[A-Za-z_]\w*)+)";
+const REQUIRE_PATTERN: &str = r#"\brequire\s*\(?\s*["']([^"']+)["']"#;
+
+/// Modules the runtime provides rather than the game
+const BUILTIN_MODULES: &[&str] = &[
+    "bit",
+    "coroutine",
+    "debug",
+    "io",
+    "love",
+    "math",
+    "os",
+    "package",
+    "socket",
+    "string",
+    "table",
+    "utf8",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => f.write_str("error"),
+            Self::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/// A problem found in a Lua file, with a 1-based line and column.
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.severity,
+            self.message
+        )
+    }
+}
+
+/// The text of `line` in `source`, and where it starts
+fn line_of(source: &str, line: usize) -> (usize, &str) {
+    let mut start = 0;
+    for (index, text) in source.split('\n').enumerate() {
+        if index + 1 == line {
+            return (start, text.trim_end_matches('\r'));
+        }
+        start += text.len() + 1;
+    }
+    (source.len(), "")
+}
+
+/// 1-based column of `text` in `line`, or the start of the line
+fn column_of(line: &str, text: &str) -> usize {
+    line.find(text)
+        .map_or(1, |offset| line[..offset].chars().count() + 1)
+}
+
+/// Checks Lua files for syntax errors and LÖVE Potion pitfalls.
+pub struct Linter {
+    lua: Lua,
+    syntax: Regex,
+    api: Regex,
+    require: Regex,
+}
+
+impl Linter {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            lua: Lua::new(),
+            syntax: Regex::new(SYNTAX_PATTERN)?,
+            api: Regex::new(API_PATTERN)?,
+            require: Regex::new(REQUIRE_PATTERN)?,
+        })
+    }
+
+    /// The parser's message for `source`, if it doesn't parse
+    fn parse(&self, source: &str) -> Option<String> {
+        let source = &source[shebang_end(source.as_bytes())..];
+        match self.lua.load(source).set_name("=chunk").into_function() {
+            Ok(_) => None,
+            Err(mlua::Error::SyntaxError { message, .. }) => Some(message),
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    /// Lua only reports the line of a syntax error, so the column is found by
+    /// cutting the line after each occurrence of the offending token until
+    /// the parser fails the same way
+    fn column(&self, source: &str, line: usize, message: &str, token: Option<&str>) -> usize {
+        let (start, text) = line_of(source, line);
+        let Some(token) = token else {
+            return text.chars().count() + 1;
+        };
+
+        for (offset, _) in text.match_indices(token) {
+            let end = start + offset + token.len();
+            if self.parse(&source[..end]).as_deref() == Some(message) {
+                return text[..offset].chars().count() + 1;
+            }
+        }
+        column_of(text, token)
+    }
+
+    /// The syntax error in `source`, if any
+    pub fn syntax(&self, path: &Path, source: &str) -> Option<Diagnostic> {
+        let message = self.parse(source)?;
+        let (line, description) = match self.syntax.captures(&message) {
+            Some(captures) => (captures[1].parse().unwrap_or(1), captures[2].to_string()),
+            None => (1, message.clone()),
+        };
+
+        let column = match description.rsplit_once(" near ") {
+            Some((_, "'<eof>'")) => self.column(source, line, &message, None),
+            Some((_, near)) => {
+                let token = near.strip_prefix('\'').and_then(|n| n.strip_suffix('\''));
+                match token {
+                    Some(token) => self.column(source, line, &message, Some(token)),
+                    None => 1,
+                }
+            }
+            None => 1,
+        };
+
+        Some(Diagnostic {
+            path: path.to_path_buf(),
+            line,
+            column,
+            severity: Severity::Error,
+            message: description,
+        })
+    }
+
+    /// Warnings about LÖVE APIs missing on `targets`, and modules that
+    /// aren't in `modules`, the paths of the bundle's Lua files
+    pub fn lint(
+        &self,
+        path: &Path,
+        source: &str,
+        targets: &[PlatformTarget],
+        modules: &HashSet<String>,
+    ) -> Vec<Diagnostic> {
+        let original: Vec<&str> = source.lines().collect();
+        // comments are stripped, each remaining line knows where it came from
+        let minified = minify(source);
+        // APIs are only looked for in code, not in strings
+        let code = blank_strings(&minified.source);
+        let mut diagnostics = Vec::new();
+
+        let lines = minified.source.lines().zip(code.lines());
+        for ((text, code), &line) in lines.zip(&minified.lines) {
+            let original = original.get(line - 1).copied().unwrap_or(text);
+            let mut warn = |found: &str, message: String| {
+                diagnostics.push(Diagnostic {
+                    path: path.to_path_buf(),
+                    line,
+                    column: column_of(original, found),
+                    severity: Severity::Warning,
+                    message,
+                })
+            };
+
+            for captures in self.api.captures_iter(code) {
+                let api = &captures[1];
+                let Some((prefix, missing)) = unsupported_api(api) else {
+                    continue;
+                };
+
+                let affected: Vec<String> = targets
+                    .iter()
+                    .filter(|target| missing.contains(target))
+                    .map(ToString::to_string)
+                    .collect();
+                if !affected.is_empty() {
+                    let message = format!(
+                        "`{prefix}` is not supported by LÖVE Potion on {}",
+                        affected.join(", ")
+                    );
+                    warn(api, message);
+                }
+            }
+
+            for captures in self.require.captures_iter(text) {
+                if let Some(message) = Self::check_require(&captures[1], modules) {
+                    warn(&captures[1], message);
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// The `love.*` APIs used in `source`, outside of comments and strings
+    pub fn apis(&self, source: &str) -> Vec<String> {
+        let code = blank_strings(&minify(source).source);
+        self.api
+            .captures_iter(&code)
+            .map(|captures| captures[1].to_string())
+            .collect()
+    }
+
+    /// Modules are looked up case-sensitively on the consoles, so a module
+    /// that loads on a PC may not on the device
+    fn check_require(module: &str, modules: &HashSet<String>) -> Option<String> {
+        let root = module.split('.').next().unwrap_or(module);
+        if BUILTIN_MODULES.contains(&root) {
+            return None;
+        }
+
+        let name = module.replace('.', "/");
+        let candidates = [format!("{name}.lua"), format!("{name}/init.lua")];
+        if candidates
+            .iter()
+            .any(|candidate| modules.contains(candidate))
+        {
+            return None;
+        }
+
+        let case_insensitive = modules.iter().find(|path| {
+            candidates
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(path))
+        });
+        match case_insensitive {
+            Some(path) => Some(format!(
+                "module `{module}` only matches `{path}` ignoring case, the console's filesystem is case-sensitive"
+            )),
+            None => Some(format!("module `{module}` is not in the bundle")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS: &[PlatformTarget] = &[PlatformTarget::Ctr, PlatformTarget::Hac];
+
+    fn lint(source: &str) -> Vec<String> {
+        let modules = HashSet::from([String::from("lib/util.lua")]);
+        let linter = Linter::new().unwrap();
+        linter
+            .lint(Path::new("main.lua"), source, TARGETS, &modules)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_unsupported_apis() {
+        let source = "local world = love.physics.newWorld(0, 0)\n";
+        assert_eq!(
+            lint(source),
+            ["main.lua:1:15: warning: `love.physics` is not supported by LÖVE Potion on ctr, hac"]
+        );
+    }
+
+    #[test]
+    fn ignores_apis_in_strings_and_comments() {
+        let source = "print(\"love.physics.newWorld\")
+print('love.video', [[
+love.mouse.getX]])
+-- love.graphics.newShader()
+";
+        assert!(lint(source).is_empty());
+        assert!(Linter::new().unwrap().apis(source).is_empty());
+    }
+
+    #[test]
+    fn checks_required_modules() {
+        let source = "local util = require(\"lib.util\")
+local Util = require('lib.Util')
+local other = require \"missing\"
+local json = require(\"love.physics\")
+";
+        assert_eq!(
+            lint(source),
+            [
+                "main.lua:2:23: warning: module `lib.Util` only matches `lib/util.lua` ignoring case, the console's filesystem is case-sensitive",
+                "main.lua:3:24: warning: module `missing` is not in the bundle",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_column_of_syntax_errors() {
+        let linter = Linter::new().unwrap();
+        let source = "local a = 1\nlocal b = = 2\n";
+        let error = linter.syntax(Path::new("main.lua"), source).unwrap();
+        assert_eq!((error.line, error.column), (2, 11));
+        assert!(linter.syntax(Path::new("main.lua"), "return 1").is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The state of a file a bundle entry was built from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub size: u64,
+    /// Modification time, in nanoseconds since the epoch
+    pub mtime: u64,
+    pub hash: String,
+}
+
+impl Record {
+    /// The record of `path`, only hashed when its size or modification time
+    /// differ from `previous`
+    pub fn read(path: &Path, previous: Option<&Record>) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+
+        if let Some(previous) = previous
+            && previous.size == size
+            && previous.mtime == mtime
+        {
+            return Ok(previous.clone());
+        }
+
+        let digest = Sha256::digest(fs::read(path)?);
+        let hash = digest.iter().map(|b| format!("{b:02x}")).collect();
+        Ok(Self { size, mtime, hash })
+    }
+}
+
+/// The files the last bundle of a target was built from, by archive path.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    /// The build settings the entries were processed with
+    pub settings: String,
+    pub entries: BTreeMap<String, Record>,
+}
+
+impl Manifest {
+    /// The manifest at `path`, or an empty one when there is none to trust
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rehashes_only_changed_files() {
+        let path = std::env::temp_dir().join(format!("nestcli-{}-record.lua", std::process::id()));
+        fs::write(&path, "print(1)").unwrap();
+        let record = Record::read(&path, None).unwrap();
+        assert_eq!(record.size, 8);
+
+        // same size and time, trusted without reading the file again
+        let previous = Record {
+            hash: String::from("previous"),
+            ..record.clone()
+        };
+        assert_eq!(Record::read(&path, Some(&previous)).unwrap(), previous);
+
+        fs::write(&path, "print(22)").unwrap();
+        let changed = Record::read(&path, Some(&previous)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(changed.size, 9);
+        assert_ne!(changed.hash, record.hash);
+    }
+
+    #[test]
+    fn round_trips_and_tolerates_bad_manifests() {
+        let path =
+            std::env::temp_dir().join(format!("nestcli-{}-manifest.toml", std::process::id()));
+        let mut manifest = Manifest {
+            settings: String::from("fast = true"),
+            entries: BTreeMap::new(),
+        };
+        manifest.entries.insert(
+            String::from("main.lua"),
+            Record {
+                size: 1,
+                mtime: 2,
+                hash: String::from("ab"),
+            },
+        );
+        manifest.save(&path).unwrap();
+
+        let loaded = Manifest::load(&path);
+        assert_eq!(loaded.settings, manifest.settings);
+        assert_eq!(loaded.entries, manifest.entries);
+
+        fs::write(&path, "not a manifest").unwrap();
+        assert!(Manifest::load(&path).entries.is_empty());
+        fs::remove_file(&path).unwrap();
+        assert!(Manifest::load(&path).settings.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+/// Lua with comments, indentation and blank lines stripped out.
+pub struct Minified {
+    pub source: String,
+    /// The original line of each line of `source`
+    pub lines: Vec<usize>,
+}
+
+/// Length of the long bracket (`[[`, `[==[`, ...) opening at `start`, if any
+fn long_bracket(bytes: &[u8], start: usize) -> Option<usize> {
+    let level = bytes[start + 1..]
+        .iter()
+        .take_while(|&&b| b == b'=')
+        .count();
+    match bytes.get(start + 1 + level) {
+        Some(b'[') => Some(level),
+        _ => None,
+    }
+}
+
+/// End of the long bracket of `level` opened at `start`, or the end of input
+fn long_bracket_end(bytes: &[u8], start: usize, level: usize) -> usize {
+    let close = [b"]".as_slice(), &b"=".repeat(level), b"]"].concat();
+    let body = start + level + 2;
+    bytes[body..]
+        .windows(close.len())
+        .position(|window| window == close)
+        .map_or(bytes.len(), |end| body + end + close.len())
+}
+
+/// End of the quoted string opened at `start`
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut index = start + 1;
+    while index < bytes.len() && bytes[index] != quote {
+        if bytes[index] == b'\\' {
+            index += 1;
+        }
+        index += 1;
+    }
+    (index + 1).min(bytes.len())
+}
+
+struct Minifier {
+    output: Vec<u8>,
+    lines: Vec<usize>,
+    line: usize,
+    space: bool,
+    newline: bool,
+}
+
+impl Minifier {
+    /// Starts a token, separating it from the previous one as needed
+    fn start(&mut self) {
+        if self.lines.is_empty() {
+            self.lines.push(self.line);
+        } else if self.newline {
+            self.output.push(b'\n');
+            self.lines.push(self.line);
+        } else if self.space {
+            self.output.push(b' ');
+        }
+        self.space = false;
+        self.newline = false;
+    }
+
+    /// Copies a token as-is, following any newlines inside it
+    fn copy(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.output.push(byte);
+            if byte == b'\n' {
+                self.line += 1;
+                self.lines.push(self.line);
+            }
+        }
+    }
+
+    /// Skips a comment, which separates tokens like whitespace
+    fn skip(&mut self, bytes: &[u8]) {
+        let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+        self.line += newlines;
+        self.newline |= newlines > 0;
+        self.space = true;
+    }
+}
+
+/// Where the code of `source` starts, after a shebang on its first line.
+///
+/// Lua only skips a shebang when loading files, so chunks loaded from memory
+/// start at its newline, keeping their line numbers
+pub fn shebang_end(source: &[u8]) -> usize {
+    match source.starts_with(b"#") {
+        true => source
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(source.len()),
+        false => 0,
+    }
+}
+
+/// Strips comments and redundant whitespace, keeping a line on its own line
+/// so the result stays valid and tracebacks can be mapped back
+pub fn minify(source: &str) -> Minified {
+    let bytes = source.as_bytes();
+    let mut minifier = Minifier {
+        output: Vec::with_capacity(bytes.len()),
+        lines: Vec::new(),
+        line: 1,
+        space: false,
+        newline: false,
+    };
+
+    let mut index = shebang_end(bytes);
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let end = match byte {
+            b'-' if bytes.get(index + 1) == Some(&b'-') => {
+                let end = match bytes.get(index + 2) {
+                    Some(b'[') => match long_bracket(bytes, index + 2) {
+                        Some(level) => long_bracket_end(bytes, index + 2, level),
+                        None => line_end(bytes, index),
+                    },
+                    _ => line_end(bytes, index),
+                };
+                minifier.skip(&bytes[index..end]);
+                end
+            }
+            b'\n' => {
+                minifier.line += 1;
+                minifier.newline = true;
+                index + 1
+            }
+            b' ' | b'\t' | b'\r' => {
+                minifier.space = true;
+                index + 1
+            }
+            b'"' | b'\'' => {
+                let end = string_end(bytes, index);
+                minifier.start();
+                minifier.copy(&bytes[index..end]);
+                end
+            }
+            b'[' => {
+                let end = match long_bracket(bytes, index) {
+                    Some(level) => long_bracket_end(bytes, index, level),
+                    None => index + 1,
+                };
+                minifier.start();
+                minifier.copy(&bytes[index..end]);
+                end
+            }
+            _ => {
+                minifier.start();
+                minifier.output.push(byte);
+                index + 1
+            }
+        };
+        index = end;
+    }
+
+    if !minifier.output.is_empty() {
+        minifier.output.push(b'\n');
+    }
+    Minified {
+        source: String::from_utf8_lossy(&minifier.output).into_owned(),
+        lines: minifier.lines,
+    }
+}
+
+/// `source` with its string literals blanked out, keeping every offset
+/// and line where it was
+pub fn blank_strings(source: &str) -> String {
+    let mut bytes = source.as_bytes().to_vec();
+    let mut index = 0;
+    while index < bytes.len() {
+        let end = match bytes[index] {
+            b'"' | b'\'' => string_end(&bytes, index),
+            b'[' => match long_bracket(&bytes, index) {
+                Some(level) => long_bracket_end(&bytes, index, level),
+                None => index + 1,
+            },
+            _ => index + 1,
+        };
+        if end > index + 1 {
+            for byte in &mut bytes[index..end] {
+                if *byte != b'\n' {
+                    *byte = b' ';
+                }
+            }
+        }
+        index = end;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn line_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |end| start + end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "#!/usr/bin/env lua
+-- a comment
+local greeting = \"hello -- not a comment\"
+
+--[[ a long
+comment ]] local long = [==[
+two ]] lines]==]
+
+    local function fail(message)
+        error(message)   -- the traceback points here
+    end
+return greeting, long, fail
+";
+
+    #[test]
+    fn skips_a_shebang_up_to_its_newline() {
+        assert_eq!(shebang_end(b"#!/usr/bin/lua\nprint(1)"), 14);
+        assert_eq!(shebang_end(b"#!/usr/bin/lua"), 14);
+        assert_eq!(shebang_end(b"print('#')"), 0);
+        assert_eq!(minify("#!/usr/bin/lua\nprint(1)\n").lines, [2]);
+    }
+
+    #[test]
+    fn strips_comments_and_whitespace() {
+        let minified = minify(SOURCE);
+        assert_eq!(
+            minified.source,
+            "local greeting = \"hello -- not a comment\"
+local long = [==[
+two ]] lines]==]
+local function fail(message)
+error(message)
+end
+return greeting, long, fail
+"
+        );
+    }
+
+    #[test]
+    fn maps_every_line_back_to_its_source_line() {
+        let minified = minify(SOURCE);
+        assert_eq!(minified.lines, [3, 6, 7, 9, 10, 11, 12]);
+
+        let source: Vec<&str> = SOURCE.lines().collect();
+        for (line, original) in minified.source.lines().zip(&minified.lines) {
+            let first = line.split(' ').next().unwrap();
+            assert!(source[original - 1].contains(first), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn maps_runtime_errors_back_to_the_source_line() {
+        let lua = mlua::Lua::new();
+        let minified = minify(SOURCE);
+        let (_, _, fail): (String, String, mlua::Function) =
+            lua.load(&minified.source).set_name("=main").eval().unwrap();
+
+        let error = fail.call::<_, ()>("boom").unwrap_err().to_string();
+        let line: usize = error
+            .split("main:")
+            .nth(1)
+            .and_then(|rest| rest.split(':').next())
+            .and_then(|line| line.parse().ok())
+            .unwrap();
+        assert_eq!(minified.lines[line - 1], 10);
+    }
+
+    #[test]
+    fn keeps_empty_sources_empty() {
+        let minified = minify("-- nothing\n\n");
+        assert!(minified.source.is_empty());
+        assert!(minified.lines.is_empty());
+    }
+}
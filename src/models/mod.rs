@@ -1,2 +1,9 @@
 pub mod bundle;
+pub mod compat;
+pub mod compression;
+pub mod ignore;
+pub mod lint;
+pub mod manifest;
+pub mod minify;
 pub mod socket;
+pub mod traceback;
@@ -1,15 +1,100 @@
 use std::{
-    io::{Read, Result},
-    net::{Ipv4Addr, Shutdown, TcpStream},
+    io::{Read, Result, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
 };
 
+use clap::ValueEnum;
+
 pub struct Socket {
     stream: TcpStream,
     buffer: Vec<u8>,
 }
 
+/// Sends input to the target while the owning [`Socket`] keeps reading.
+pub struct SocketWriter {
+    stream: TcpStream,
+}
+
 const SOCKET_BUFFER_SIZE: usize = 0x1000;
 
+/// Marks the end of a chunk of Lua for the console on the target
+const CHUNK_TERMINATOR: u8 = 0;
+
+/// Structured log messages are framed as:
+///
+/// | size | field                                      |
+/// |------|--------------------------------------------|
+/// | 3    | magic, `\x1eLP`                            |
+/// | 1    | version                                    |
+/// | 1    | level, 0 (trace) to 4 (error)              |
+/// | 8    | milliseconds since the game started, LE    |
+/// | 1    | tag length                                 |
+/// | n    | tag                                        |
+/// | 4    | payload length, LE                         |
+/// | n    | payload                                    |
+///
+/// Anything outside of a frame is legacy text and is passed through as-is.
+const FRAME_MAGIC: &[u8] = b"\x1eLP";
+const FRAME_VERSION: u8 = 1;
+const FRAME_HEADER_SIZE: usize = 14;
+const MAX_PAYLOAD_SIZE: usize = 0x100000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Trace),
+            1 => Some(Self::Debug),
+            2 => Some(Self::Info),
+            3 => Some(Self::Warn),
+            4 => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub timestamp: u64,
+    pub tag: String,
+    pub payload: String,
+}
+
+pub enum Packet {
+    Text(Vec<u8>),
+    Message(Message),
+}
+
+enum Frame {
+    Incomplete,
+    Invalid,
+    Complete(Message, usize),
+}
+
+/// Splits the incoming stream into structured messages and legacy text.
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+}
+
 impl Drop for Socket {
     fn drop(&mut self) {
         if let Err(result) = self.stream.shutdown(Shutdown::Both) {
@@ -19,12 +104,17 @@ impl Drop for Socket {
 }
 
 impl Socket {
-    pub fn new(address: (Ipv4Addr, u16)) -> Result<Self> {
+    pub fn new(address: SocketAddr) -> Result<Self> {
         let stream = TcpStream::connect(address)?;
         let buffer = vec![0; SOCKET_BUFFER_SIZE];
         Ok(Self { stream, buffer })
     }
 
+    pub fn writer(&self) -> Result<SocketWriter> {
+        let stream = self.stream.try_clone()?;
+        Ok(SocketWriter { stream })
+    }
+
     pub fn read(&mut self) -> Result<Option<&[u8]>> {
         match self.stream.read(&mut self.buffer) {
             Ok(0) => Ok(None),
@@ -33,3 +123,226 @@ impl Socket {
         }
     }
 }
+
+impl SocketWriter {
+    pub fn send(&mut self, chunk: &str) -> Result<()> {
+        self.stream.write_all(chunk.as_bytes())?;
+        self.stream.write_all(&[CHUNK_TERMINATOR])
+    }
+}
+
+fn parse_frame(data: &[u8]) -> Frame {
+    if data.len() < FRAME_HEADER_SIZE {
+        return Frame::Incomplete;
+    }
+
+    let level = match Level::from_u8(data[4]) {
+        Some(level) if data[3] == FRAME_VERSION => level,
+        _ => return Frame::Invalid,
+    };
+
+    let timestamp = u64::from_le_bytes(data[5..13].try_into().expect("8 bytes"));
+    let tag_end = FRAME_HEADER_SIZE + data[13] as usize;
+    let Some(length) = data.get(tag_end..tag_end + 4) else {
+        return Frame::Incomplete;
+    };
+
+    let length = u32::from_le_bytes(length.try_into().expect("4 bytes")) as usize;
+    if length > MAX_PAYLOAD_SIZE {
+        return Frame::Invalid;
+    }
+
+    let payload_end = tag_end + 4 + length;
+    let Some(payload) = data.get(tag_end + 4..payload_end) else {
+        return Frame::Incomplete;
+    };
+
+    let message = Message {
+        level,
+        timestamp,
+        tag: String::from_utf8_lossy(&data[FRAME_HEADER_SIZE..tag_end]).into_owned(),
+        payload: String::from_utf8_lossy(payload).into_owned(),
+    };
+    Frame::Complete(message, payload_end)
+}
+
+/// Length of the longest suffix of `data` that could be the start of a frame
+fn partial_magic(data: &[u8]) -> usize {
+    (1..FRAME_MAGIC.len())
+        .rev()
+        .find(|&len| data.ends_with(&FRAME_MAGIC[..len]))
+        .unwrap_or(0)
+}
+
+impl Decoder {
+    pub fn decode(&mut self, data: &[u8]) -> Vec<Packet> {
+        self.buffer.extend_from_slice(data);
+        let mut packets = Vec::new();
+
+        loop {
+            let start = self
+                .buffer
+                .windows(FRAME_MAGIC.len())
+                .position(|window| window == FRAME_MAGIC);
+
+            let Some(start) = start else {
+                let end = self.buffer.len() - partial_magic(&self.buffer);
+                if end > 0 {
+                    packets.push(Packet::Text(self.buffer.drain(..end).collect()));
+                }
+                break;
+            };
+
+            if start > 0 {
+                packets.push(Packet::Text(self.buffer.drain(..start).collect()));
+            }
+
+            match parse_frame(&self.buffer) {
+                Frame::Incomplete => break,
+                Frame::Invalid => packets.push(Packet::Text(self.buffer.drain(..1).collect())),
+                Frame::Complete(message, length) => {
+                    self.buffer.drain(..length);
+                    packets.push(Packet::Message(message));
+                }
+            }
+        }
+        packets
+    }
+
+    /// Whatever is left over once the connection closes is legacy text
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(level: u8, tag: &str, payload: &str) -> Vec<u8> {
+        let mut data = FRAME_MAGIC.to_vec();
+        data.push(FRAME_VERSION);
+        data.push(level);
+        data.extend_from_slice(&1234u64.to_le_bytes());
+        data.push(tag.len() as u8);
+        data.extend_from_slice(tag.as_bytes());
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(payload.as_bytes());
+        data
+    }
+
+    /// Decodes `chunks` one read at a time, joining consecutive text
+    fn decode(chunks: &[&[u8]]) -> Vec<String> {
+        let mut decoder = Decoder::default();
+        let mut packets: Vec<Packet> = chunks
+            .iter()
+            .flat_map(|chunk| decoder.decode(chunk))
+            .collect();
+        packets.push(Packet::Text(decoder.finish()));
+
+        let mut decoded = Vec::new();
+        let mut text = Vec::new();
+        for packet in packets {
+            match packet {
+                Packet::Text(data) => text.extend(data),
+                Packet::Message(message) => {
+                    if !text.is_empty() {
+                        decoded.push(String::from_utf8_lossy(&text).into_owned());
+                        text.clear();
+                    }
+                    decoded.push(format!(
+                        "{} {} [{}] {}",
+                        message.level.name(),
+                        message.timestamp,
+                        message.tag,
+                        message.payload
+                    ));
+                }
+            }
+        }
+        if !text.is_empty() {
+            decoded.push(String::from_utf8_lossy(&text).into_owned());
+        }
+        decoded
+    }
+
+    #[test]
+    fn decodes_a_frame() {
+        let data = frame(2, "audio", "loaded");
+        assert_eq!(data.len(), FRAME_HEADER_SIZE + 5 + 4 + 6);
+        assert_eq!(decode(&[&data]), ["INFO 1234 [audio] loaded"]);
+    }
+
+    #[test]
+    fn decodes_frames_split_across_reads() {
+        let mut data = frame(4, "main", "first");
+        data.extend(frame(0, "", "second"));
+
+        for split in 1..data.len() {
+            let (head, tail) = data.split_at(split);
+            assert_eq!(
+                decode(&[head, tail]),
+                ["ERROR 1234 [main] first", "TRACE 1234 [] second"],
+                "split at {split}"
+            );
+        }
+
+        let bytes: Vec<&[u8]> = data.chunks(1).collect();
+        assert_eq!(
+            decode(&bytes),
+            ["ERROR 1234 [main] first", "TRACE 1234 [] second"]
+        );
+    }
+
+    #[test]
+    fn passes_bad_magic_through_as_text() {
+        let mut data = frame(2, "tag", "payload");
+        data[2] = b'X';
+        assert_eq!(decode(&[&data]), [String::from_utf8_lossy(&data)]);
+    }
+
+    #[test]
+    fn passes_bad_headers_through_as_text() {
+        let mut data = frame(2, "tag", "payload");
+        data[3] = FRAME_VERSION + 1;
+        assert_eq!(decode(&[&data]), [String::from_utf8_lossy(&data)]);
+
+        let mut data = frame(2, "tag", "payload");
+        data[4] = 5;
+        assert_eq!(decode(&[&data]), [String::from_utf8_lossy(&data)]);
+    }
+
+    #[test]
+    fn rejects_oversize_payloads() {
+        let mut data = frame(2, "tag", "");
+        let length = data.len() - 4;
+        data[length..].copy_from_slice(&(MAX_PAYLOAD_SIZE as u32 + 1).to_le_bytes());
+        data.extend(frame(3, "next", "ok"));
+
+        let text = String::from_utf8_lossy(&data[..length + 4]).into_owned();
+        assert_eq!(decode(&[&data]), [text, "WARN 1234 [next] ok".to_string()]);
+    }
+
+    #[test]
+    fn keeps_an_incomplete_frame_as_text_on_finish() {
+        let data = frame(2, "tag", "payload");
+        let partial = &data[..data.len() - 1];
+        assert_eq!(decode(&[partial]), [String::from_utf8_lossy(partial)]);
+    }
+
+    #[test]
+    fn separates_plain_text_and_frames() {
+        let mut data = b"legacy line\n".to_vec();
+        data.extend(frame(1, "gfx", "frame"));
+        data.extend(b"more \x1e text\n\x1eL");
+
+        assert_eq!(
+            decode(&[&data[..15], &data[15..]]),
+            [
+                "legacy line\n",
+                "DEBUG 1234 [gfx] frame",
+                "more \x1e text\n\x1eL"
+            ]
+        );
+    }
+}
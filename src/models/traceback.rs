@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::models::bundle::SOURCE_MAP_NAME;
+
+/// Paths in tracebacks are relative to where the game is mounted on the console
+const MOUNT_PREFIX: &str = "game/";
+
+const FRAME_PATTERN: &str = r"(?<path>[\w\-./]+\.lua):(?<line>\d+)";
+
+/// A `file:line` reference to the project tree found in a line of output.
+pub struct Frame {
+    pub range: Range<usize>,
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// Maps the paths inside a bundle back to the project tree on disk.
+pub struct SourceMap {
+    root: PathBuf,
+    source: PathBuf,
+    pattern: Regex,
+    lines: HashMap<String, Vec<usize>>,
+}
+
+fn read_lines(path: &Path) -> Result<HashMap<String, Vec<usize>>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// The line maps of minified Lua written next to each target's bundle in
+/// `out_dir`, leaving out the ones that can't be read
+fn load_lines(out_dir: &Path) -> HashMap<String, Vec<usize>> {
+    let mut lines = HashMap::new();
+    let Ok(targets) = std::fs::read_dir(out_dir) else {
+        return lines;
+    };
+
+    for target in targets.filter_map(|entry| entry.ok()) {
+        let path = target.path().join(SOURCE_MAP_NAME);
+        if !path.is_file() {
+            continue;
+        }
+        match read_lines(&path) {
+            Ok(map) => lines.extend(map),
+            Err(e) => eprintln!("Skipping {}: {e}", path.display()),
+        }
+    }
+    lines
+}
+
+impl SourceMap {
+    /// `out_dir` is where the bundles were written, relative to `root`
+    pub fn new(root: &Path, source: &str, out_dir: &Path) -> Result<Self> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            source: root.join(source),
+            pattern: Regex::new(FRAME_PATTERN)?,
+            lines: load_lines(&root.join(out_dir)),
+        })
+    }
+
+    fn resolve(&self, relative: &str) -> Option<PathBuf> {
+        [self.source.join(relative), self.root.join(relative)]
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// The line in the original source of a line of minified Lua
+    fn original_line(&self, relative: &str, line: usize) -> usize {
+        self.lines
+            .get(relative)
+            .and_then(|lines| lines.get(line.checked_sub(1)?))
+            .copied()
+            .unwrap_or(line)
+    }
+
+    pub fn frames(&self, text: &str) -> Vec<Frame> {
+        self.pattern
+            .captures_iter(text)
+            .filter_map(|caps| {
+                let path = &caps["path"];
+                let relative = path.strip_prefix(MOUNT_PREFIX).unwrap_or(path);
+                let line = self.original_line(relative, caps["line"].parse().ok()?);
+                let path = self.resolve(relative)?;
+                Some(Frame {
+                    range: caps.get(0)?.range(),
+                    path,
+                    line,
+                })
+            })
+            .collect()
+    }
+
+    /// Path of a frame relative to the project root, for display
+    pub fn display_path<'a>(&self, frame: &'a Frame) -> &'a Path {
+        frame.path.strip_prefix(&self.root).unwrap_or(&frame.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn maps_lines_from_any_output_directory() {
+        let root = std::env::temp_dir().join(format!("nestcli-traceback-{}", std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.lua"), "").unwrap();
+        for (target, map) in [("ctr", "\"main.lua\" = [4, 9]\n"), ("hac", "not a map")] {
+            fs::create_dir_all(root.join("dist").join(target)).unwrap();
+            fs::write(root.join("dist").join(target).join(SOURCE_MAP_NAME), map).unwrap();
+        }
+
+        // the unreadable map is skipped rather than failing
+        let sources = SourceMap::new(&root, "src", Path::new("dist")).unwrap();
+        let frames = sources.frames("game/main.lua:2: oops");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].line, 9);
+        assert_eq!(frames[0].path, root.join("src/main.lua"));
+    }
+}
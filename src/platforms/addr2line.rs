@@ -1,14 +1,16 @@
-use std::fs::File;
-use std::io::{BufReader, Write, stdout};
+use std::io::{Write, stdout};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use aho_corasick::AhoCorasick;
 use anyhow::Result;
 
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::detect::{Container, detect_platform};
+use crate::platforms::symbolizer::Symbolizer;
+
 pub struct Candidate {
     binary: &'static str,
-    pub magic: &'static [u8],
+    pub platform: PlatformTarget,
     args: &'static [&'static str],
     search_path: &'static str,
     device: &'static str,
@@ -17,19 +19,23 @@ pub struct Candidate {
 }
 
 impl Candidate {
-    pub fn offset_address(&self, address: &str) -> Result<String> {
+    pub fn offset(&self, address: &str) -> Result<u32> {
         let value = u32::from_str_radix(address.trim_start_matches("0x"), 16)?;
-        Ok(format!(
-            "0x{:08X}",
-            value - self.runtime_base + self.elf_text_base
-        ))
+        match value.checked_sub(self.runtime_base) {
+            Some(value) => Ok(value + self.elf_text_base),
+            None => anyhow::bail!("Address {address} is below the runtime base"),
+        }
+    }
+
+    pub fn offset_address(&self, address: &str) -> Result<String> {
+        Ok(format!("0x{:08X}", self.offset(address)?))
     }
 }
 
 const CANDIDATES: [Candidate; 3] = [
     Candidate {
         binary: "arm-none-eabi-addr2line",
-        magic: b"3dsx_crt0.o",
+        platform: PlatformTarget::Ctr,
         args: &["arm"],
         search_path: "devkitARM/bin",
         device: "Nintendo 3DS",
@@ -38,7 +44,7 @@ const CANDIDATES: [Candidate; 3] = [
     },
     Candidate {
         binary: "aarch64-none-elf-addr2line",
-        magic: b"switch_crt0.o",
+        platform: PlatformTarget::Hac,
         args: &[""],
         search_path: "devkitA64/bin",
         device: "Nintendo Switch",
@@ -47,7 +53,7 @@ const CANDIDATES: [Candidate; 3] = [
     },
     Candidate {
         binary: "powerpc-eabi-addr2line",
-        magic: b"crt0_rpx.o",
+        platform: PlatformTarget::Cafe,
         args: &[""],
         search_path: "devkitPPC/bin",
         device: "Nintendo Wiiᵘ",
@@ -56,23 +62,62 @@ const CANDIDATES: [Candidate; 3] = [
     },
 ];
 
-pub fn find_candidate(filepath: &Path) -> Result<Option<&Candidate>> {
-    let reader = match File::open(filepath) {
-        Ok(file) => BufReader::new(file),
-        Err(_) => anyhow::bail!("Failed to open file: {filepath:#?}"),
-    };
+fn candidate_for(platform: PlatformTarget) -> &'static Candidate {
+    CANDIDATES
+        .iter()
+        .find(|candidate| candidate.platform == platform)
+        .expect("every platform has a candidate")
+}
+
+pub fn find_candidate(
+    filepath: &Path,
+    platform: Option<PlatformTarget>,
+) -> Result<&'static Candidate> {
+    if let Some(platform) = platform {
+        return Ok(candidate_for(platform));
+    }
 
-    let patterns = CANDIDATES.iter().map(|c| c.magic).collect::<Vec<&[u8]>>();
-    let ac = AhoCorasick::new(&patterns)?;
+    let detection = detect_platform(filepath)?;
+    let candidate = candidate_for(detection.platform);
 
-    if let Some(matched) = ac.stream_find_iter(reader).flatten().next() {
-        let index = matched.pattern().as_usize();
-        return Ok(Some(&CANDIDATES[index]));
+    if detection.container != Container::Elf {
+        anyhow::bail!(
+            "{filepath:#?} is a {} {} without debug info, use the .elf it was built from",
+            candidate.device,
+            detection.container.name()
+        );
     }
-    Ok(None)
+    Ok(candidate)
 }
 
 impl Candidate {
+    pub fn symbolize(&self, filepath: &Path, addresses: &[String]) -> Result<()> {
+        if !filepath.exists() || !filepath.is_file() {
+            anyhow::bail!("File not found: {filepath:#?}");
+        }
+
+        if addresses.is_empty() {
+            anyhow::bail!("No addresses provided");
+        }
+
+        let symbolizer = Symbolizer::new(filepath)?;
+        println!("[{}: built-in]", self.device);
+
+        let mut out = stdout().lock();
+        for address in addresses {
+            let offset = self.offset(address)?;
+            let frames = symbolizer.resolve(offset.into())?;
+            for (index, frame) in frames.iter().enumerate() {
+                if index == 0 {
+                    writeln!(out, "0x{offset:08x}: {frame}")?;
+                } else {
+                    writeln!(out, " (inlined by) {frame}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn command(&self, filepath: &Path, addresses: &[String]) -> Result<()> {
         if !filepath.exists() || !filepath.is_file() {
             anyhow::bail!("File not found: {filepath:#?}");
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use regex::{Captures, Regex};
+
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::addr2line::{Candidate, find_candidate};
+use crate::platforms::symbolizer::Symbolizer;
+
+/// Hex addresses, register dumps (`PC: 00123456`) and stack trace entries (`#2 00123456`)
+const ADDRESS_PATTERN: &str = concat!(
+    r"\b0x(?<hex>[0-9A-Fa-f]{1,8})\b",
+    r"|\b(?:PC|LR|SP|pc|lr|sp)\s*[:=]\s*(?<reg>[0-9A-Fa-f]{8})\b",
+    r"|#\d+\s+(?<frame>[0-9A-Fa-f]{8})\b",
+);
+
+/// Inlines symbol information next to the addresses found in crash logs.
+pub struct Annotator {
+    candidate: &'static Candidate,
+    symbolizer: Symbolizer,
+    pattern: Regex,
+    cache: HashMap<u32, Option<String>>,
+}
+
+impl Annotator {
+    pub fn new(filepath: &Path, platform: Option<PlatformTarget>) -> Result<Self> {
+        let filepath = std::path::absolute(filepath)?;
+        let candidate = find_candidate(&filepath, platform)?;
+        let symbolizer = Symbolizer::new(&filepath)?;
+        let pattern = Regex::new(ADDRESS_PATTERN)?;
+        Ok(Self {
+            candidate,
+            symbolizer,
+            pattern,
+            cache: HashMap::new(),
+        })
+    }
+
+    fn lookup(&mut self, address: &str) -> Option<String> {
+        let offset = self.candidate.offset(address).ok()?;
+        if let Some(cached) = self.cache.get(&offset) {
+            return cached.clone();
+        }
+
+        // only annotate addresses that land inside a known function
+        let resolved = self
+            .symbolizer
+            .resolve(offset.into())
+            .ok()
+            .and_then(|frames| frames.into_iter().next())
+            .filter(|frame| frame.function.is_some())
+            .map(|frame| frame.to_string());
+
+        self.cache.insert(offset, resolved.clone());
+        resolved
+    }
+
+    pub fn annotate(&mut self, line: &str) -> String {
+        let pattern = self.pattern.clone();
+        let annotated = pattern.replace_all(line, |caps: &Captures| {
+            let matched = &caps[0];
+            let address = ["hex", "reg", "frame"]
+                .iter()
+                .find_map(|name| caps.name(name))
+                .map(|m| m.as_str());
+
+            match address.and_then(|address| self.lookup(address)) {
+                Some(symbol) => format!("{matched} ({symbol})"),
+                None => matched.to_string(),
+            }
+        });
+        annotated.into_owned()
+    }
+}
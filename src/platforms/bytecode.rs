@@ -0,0 +1,303 @@
+use anyhow::{Result, bail};
+use mlua::Lua;
+
+use crate::config::bundle::PlatformTarget;
+use crate::models::minify::shebang_end;
+
+/// `ESC Lua`, version 5.1 and the official format
+const HEADER_PREFIX: &[u8] = b"\x1bLua\x51\x00";
+const HEADER_SIZE: usize = 12;
+
+const LUA_TNIL: u8 = 0;
+const LUA_TBOOLEAN: u8 = 1;
+const LUA_TNUMBER: u8 = 3;
+const LUA_TSTRING: u8 = 4;
+
+/// How a Lua 5.1 build lays out the values in its bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    little_endian: bool,
+    int_size: usize,
+    size_t_size: usize,
+}
+
+impl Layout {
+    fn for_platform(platform: PlatformTarget) -> Self {
+        match platform {
+            PlatformTarget::Ctr => Self {
+                little_endian: true,
+                int_size: 4,
+                size_t_size: 4,
+            },
+            PlatformTarget::Hac => Self {
+                little_endian: true,
+                int_size: 4,
+                size_t_size: 8,
+            },
+            PlatformTarget::Cafe => Self {
+                little_endian: false,
+                int_size: 4,
+                size_t_size: 4,
+            },
+        }
+    }
+
+    fn parse(header: &[u8]) -> Result<Self> {
+        if header.len() < HEADER_SIZE || !header.starts_with(HEADER_PREFIX) {
+            bail!("Not Lua 5.1 bytecode");
+        }
+        // instructions are 32-bit and numbers are doubles on every target
+        if header[9] != 4 || header[10] != 8 || header[11] != 0 {
+            bail!("Unsupported Lua number or instruction format");
+        }
+        Ok(Self {
+            little_endian: header[6] == 1,
+            int_size: header[7] as usize,
+            size_t_size: header[8] as usize,
+        })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = HEADER_PREFIX.to_vec();
+        header.extend_from_slice(&[
+            u8::from(self.little_endian),
+            self.int_size as u8,
+            self.size_t_size as u8,
+            4,
+            8,
+            0,
+        ]);
+        header
+    }
+}
+
+/// Rewrites bytecode dumped by one Lua 5.1 build for another.
+struct Transcoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    from: Layout,
+    to: Layout,
+    output: Vec<u8>,
+}
+
+impl Transcoder<'_> {
+    fn take(&mut self, size: usize) -> Result<&[u8]> {
+        match self.data.get(self.position..self.position + size) {
+            Some(bytes) => {
+                self.position += size;
+                Ok(bytes)
+            }
+            None => bail!("Truncated Lua bytecode"),
+        }
+    }
+
+    fn read_uint(&mut self, size: usize) -> Result<u64> {
+        let little_endian = self.from.little_endian;
+        let bytes = self.take(size)?;
+        let value = match little_endian {
+            true => bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, &b| acc << 8 | u64::from(b)),
+            false => bytes.iter().fold(0, |acc, &b| acc << 8 | u64::from(b)),
+        };
+        Ok(value)
+    }
+
+    fn write_uint(&mut self, value: u64, size: usize) -> Result<()> {
+        if size < 8 && value >> (size * 8) != 0 {
+            bail!("Value {value} does not fit the target's Lua");
+        }
+        let bytes = value.to_le_bytes();
+        match self.to.little_endian {
+            true => self.output.extend_from_slice(&bytes[..size]),
+            false => self.output.extend(bytes[..size].iter().rev()),
+        }
+        Ok(())
+    }
+
+    fn int(&mut self) -> Result<u64> {
+        let value = self.read_uint(self.from.int_size)?;
+        self.write_uint(value, self.to.int_size)?;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let value = self.take(1)?[0];
+        self.output.push(value);
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<()> {
+        let size = self.read_uint(self.from.size_t_size)?;
+        self.write_uint(size, self.to.size_t_size)?;
+        let bytes = self.take(size as usize)?.to_vec();
+        self.output.extend_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Instructions and numbers keep their size, only their byte order changes
+    fn word(&mut self, size: usize) -> Result<()> {
+        let value = self.read_uint(size)?;
+        self.write_uint(value, size)
+    }
+
+    fn function(&mut self) -> Result<()> {
+        self.string()?; // source
+        self.int()?; // linedefined
+        self.int()?; // lastlinedefined
+        for _ in 0..4 {
+            // nups, numparams, is_vararg, maxstacksize
+            self.byte()?;
+        }
+
+        for _ in 0..self.int()? {
+            self.word(4)?;
+        }
+
+        for _ in 0..self.int()? {
+            match self.byte()? {
+                LUA_TNIL => {}
+                LUA_TBOOLEAN => {
+                    self.byte()?;
+                }
+                LUA_TNUMBER => self.word(8)?,
+                LUA_TSTRING => self.string()?,
+                kind => bail!("Unknown constant type {kind} in Lua bytecode"),
+            }
+        }
+
+        for _ in 0..self.int()? {
+            self.function()?;
+        }
+
+        // debug info: line numbers, locals and upvalue names
+        for _ in 0..self.int()? {
+            self.int()?;
+        }
+        for _ in 0..self.int()? {
+            self.string()?;
+            self.int()?;
+            self.int()?;
+        }
+        for _ in 0..self.int()? {
+            self.string()?;
+        }
+        Ok(())
+    }
+}
+
+fn transcode(bytecode: &[u8], to: Layout) -> Result<Vec<u8>> {
+    let from = Layout::parse(bytecode)?;
+    if from == to {
+        return Ok(bytecode.to_vec());
+    }
+
+    let mut transcoder = Transcoder {
+        data: bytecode,
+        position: HEADER_SIZE,
+        from,
+        to,
+        output: to.header(),
+    };
+    transcoder.function()?;
+    Ok(transcoder.output)
+}
+
+/// Compiles Lua source to bytecode for the runtime on `platform`, keeping
+/// debug info so tracebacks still have line numbers
+pub fn compile(
+    lua: &Lua,
+    source: &[u8],
+    chunkname: &str,
+    platform: PlatformTarget,
+) -> Result<Vec<u8>> {
+    let source = &source[shebang_end(source)..];
+    let function = match lua.load(source).set_name(chunkname).into_function() {
+        Ok(function) => function,
+        Err(e) => bail!("{e}"),
+    };
+    transcode(&function.dump(false), Layout::for_platform(platform))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "#!/usr/bin/env lua
+local values = { 1.5, -2, \"text\", true, false, nil }
+local function describe(value)
+    return type(value) .. \":\" .. tostring(value)
+end
+local result = {}
+for i = 1, 6 do
+    result[#result + 1] = describe(values[i])
+end
+return table.concat(result, \",\")
+";
+    const RESULT: &str = "number:1.5,number:-2,string:text,boolean:true,boolean:false,nil:nil";
+
+    const ALL_TARGETS: &[PlatformTarget] = &[
+        PlatformTarget::Ctr,
+        PlatformTarget::Hac,
+        PlatformTarget::Cafe,
+    ];
+
+    fn host(lua: &Lua) -> Layout {
+        let function = lua.load("return").into_function().unwrap();
+        Layout::parse(&function.dump(false)).unwrap()
+    }
+
+    #[test]
+    fn writes_the_header_of_each_target() {
+        let lua = Lua::new();
+        for &platform in ALL_TARGETS {
+            let bytecode = compile(&lua, SOURCE.as_bytes(), "=main", platform).unwrap();
+            let layout = Layout::for_platform(platform);
+            assert_eq!(&bytecode[..HEADER_SIZE], layout.header());
+            assert_eq!(Layout::parse(&bytecode).unwrap(), layout);
+        }
+        assert_eq!(
+            Layout::for_platform(PlatformTarget::Cafe).header(),
+            b"\x1bLua\x51\x00\x00\x04\x04\x04\x08\x00"
+        );
+        assert_eq!(
+            Layout::for_platform(PlatformTarget::Hac).header(),
+            b"\x1bLua\x51\x00\x01\x04\x08\x04\x08\x00"
+        );
+    }
+
+    #[test]
+    fn transcoded_bytecode_loads_after_a_round_trip() {
+        let lua = unsafe { Lua::unsafe_new() };
+        let host = host(&lua);
+        for &platform in ALL_TARGETS {
+            let bytecode = compile(&lua, SOURCE.as_bytes(), "=main", platform).unwrap();
+            let bytecode = transcode(&bytecode, host).unwrap();
+            let result: String = lua.load(&bytecode).eval().unwrap();
+            assert_eq!(result, RESULT, "{platform}");
+        }
+    }
+
+    #[test]
+    fn keeps_line_numbers_and_the_shebang_line() {
+        let lua = unsafe { Lua::unsafe_new() };
+        let host = host(&lua);
+        let source = "#!/usr/bin/env lua\n\nerror(\"boom\")\n";
+        for &platform in ALL_TARGETS {
+            let bytecode = compile(&lua, source.as_bytes(), "=main", platform).unwrap();
+            let bytecode = transcode(&bytecode, host).unwrap();
+            let error = lua.load(&bytecode).exec().unwrap_err().to_string();
+            assert!(error.contains("main:3: boom"), "{platform}: {error}");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_bytecode() {
+        let lua = Lua::new();
+        let bytecode = compile(&lua, SOURCE.as_bytes(), "=main", PlatformTarget::Hac).unwrap();
+        let to = Layout::for_platform(PlatformTarget::Cafe);
+        assert!(transcode(&bytecode[..bytecode.len() - 1], to).is_err());
+        assert!(transcode(b"return 1", to).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use object::{Architecture, Object, ObjectSegment};
+
+use crate::config::bundle::PlatformTarget;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const THREEDSX_MAGIC: &[u8] = b"3DSX";
+const NRO_MAGIC: &[u8] = b"NRO0";
+const NRO_MAGIC_OFFSET: usize = 0x10;
+const WUHB_MAGIC: &[u8] = b"WUHB";
+
+/// `EI_OSABI` and `EI_ABIVERSION` of an RPX/RPL are set to 0xCA and 0xFE
+const RPX_ABI: [u8; 2] = [0xCA, 0xFE];
+
+/// The file formats a build can be handed to us in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Elf,
+    Rpx,
+    Threedsx,
+    Nro,
+    Wuhb,
+}
+
+impl Container {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Elf => "ELF",
+            Self::Rpx => "RPX",
+            Self::Threedsx => "3DSX",
+            Self::Nro => "NRO",
+            Self::Wuhb => "WUHB",
+        }
+    }
+}
+
+pub struct Detection {
+    pub platform: PlatformTarget,
+    pub container: Container,
+}
+
+struct ElfLayout {
+    architecture: Architecture,
+    is_64: bool,
+    is_little_endian: bool,
+    load_address: Option<u64>,
+}
+
+impl ElfLayout {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let file = object::File::parse(data)?;
+        let load_address = file.segments().map(|s| s.address()).min();
+        Ok(Self {
+            architecture: file.architecture(),
+            is_64: file.is_64(),
+            is_little_endian: file.is_little_endian(),
+            load_address,
+        })
+    }
+
+    fn expect(&self, is_64: bool, is_little_endian: bool, load_address: u64) -> Result<()> {
+        let bits = |is_64| if is_64 { "64-bit" } else { "32-bit" };
+        let endian = |little| {
+            if little {
+                "little-endian"
+            } else {
+                "big-endian"
+            }
+        };
+
+        if self.is_64 != is_64 || self.is_little_endian != is_little_endian {
+            bail!(
+                "{:?} ELF is {} {}, expected {} {}",
+                self.architecture,
+                bits(self.is_64),
+                endian(self.is_little_endian),
+                bits(is_64),
+                endian(is_little_endian)
+            );
+        }
+
+        match self.load_address {
+            Some(address) if address == load_address => Ok(()),
+            Some(address) => bail!(
+                "{:?} ELF loads at 0x{address:08X}, expected 0x{load_address:08X}",
+                self.architecture
+            ),
+            None => bail!("{:?} ELF has no loadable segments", self.architecture),
+        }
+    }
+}
+
+fn detect_elf(data: &[u8]) -> Result<PlatformTarget> {
+    let layout = ElfLayout::parse(data)?;
+
+    let platform = match layout.architecture {
+        Architecture::Arm => PlatformTarget::Ctr,
+        Architecture::Aarch64 => PlatformTarget::Hac,
+        Architecture::PowerPc => PlatformTarget::Cafe,
+        other => bail!("Unsupported ELF machine type: {other:?}"),
+    };
+
+    // devkitARM and devkitPPC also target the GBA, DS, GameCube and Wii,
+    // so the machine type alone is not enough to tell the platforms apart
+    let checked = match platform {
+        PlatformTarget::Ctr => layout.expect(false, true, 0x0010_0000),
+        PlatformTarget::Hac => layout.expect(true, true, 0x0000_0000),
+        PlatformTarget::Cafe => layout.expect(false, false, 0x0200_0000),
+    };
+
+    if let Err(reason) = checked {
+        bail!("Could not confirm the platform: {reason}. Use --platform to override.");
+    }
+    Ok(platform)
+}
+
+pub fn detect_platform(filepath: &Path) -> Result<Detection> {
+    let data = match fs::read(filepath) {
+        Ok(data) => data,
+        Err(_) => bail!("Failed to open file: {filepath:#?}"),
+    };
+
+    let detection = |platform, container| {
+        Ok(Detection {
+            platform,
+            container,
+        })
+    };
+
+    if data.starts_with(THREEDSX_MAGIC) {
+        return detection(PlatformTarget::Ctr, Container::Threedsx);
+    }
+
+    if data.get(NRO_MAGIC_OFFSET..NRO_MAGIC_OFFSET + NRO_MAGIC.len()) == Some(NRO_MAGIC) {
+        return detection(PlatformTarget::Hac, Container::Nro);
+    }
+
+    if data.starts_with(WUHB_MAGIC) {
+        return detection(PlatformTarget::Cafe, Container::Wuhb);
+    }
+
+    if !data.starts_with(ELF_MAGIC) {
+        bail!("{filepath:#?} is not an ELF, 3DSX, NRO, RPX or WUHB file");
+    }
+
+    if data.get(7..9) == Some(&RPX_ABI) {
+        return detection(PlatformTarget::Cafe, Container::Rpx);
+    }
+
+    detection(detect_elf(&data)?, Container::Elf)
+}
@@ -0,0 +1,31 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{Result, bail};
+
+/// Finds one of the devkitPro host tools
+fn tool(name: &str) -> Result<PathBuf> {
+    let binary = match std::env::var("DEVKITPRO") {
+        Ok(val) => PathBuf::from(val).join("tools/bin").join(name),
+        Err(_) => PathBuf::from(name),
+    };
+
+    match which::which(&binary) {
+        Ok(path) => Ok(path),
+        Err(_) => bail!("{name} not found, install devkitPro's tools or set DEVKITPRO"),
+    }
+}
+
+pub fn run_tool(name: &str, args: &[&OsStr]) -> Result<()> {
+    let output = match Command::new(tool(name)?).args(args).output() {
+        Ok(output) => output,
+        Err(e) => bail!("Failed to execute {name}: {e}"),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{name} failed: {}", stderr.trim());
+    }
+    Ok(())
+}
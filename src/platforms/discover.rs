@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::link::Protocol;
+
+/// LÖVE Potion's debug server answers this broadcast with `nestdbg:<platform>`
+const DEBUG_PING: &[u8] = b"nestdbg";
+const DEBUG_REPLY_PREFIX: &[u8] = b"nestdbg:";
+const DEBUG_SERVICE: &str = "debug";
+
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+const LINK_PROTOCOLS: [Protocol; 2] = [Protocol::ThreedsLink, Protocol::NxLink];
+
+/// A console that answered one of the discovery broadcasts.
+pub struct Device {
+    pub address: Ipv4Addr,
+    pub platform: Option<PlatformTarget>,
+    pub services: Vec<&'static str>,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let platform = self.platform.map_or("?".to_string(), |p| p.to_string());
+        write!(
+            f,
+            "{:<15} {platform:<5} {}",
+            self.address,
+            self.services.join(", ")
+        )
+    }
+}
+
+fn identify(reply: &[u8]) -> Option<(Option<PlatformTarget>, &'static str)> {
+    for protocol in LINK_PROTOCOLS {
+        if let Some((_, expected)) = protocol.discovery()
+            && reply == expected
+        {
+            return Some((Some(protocol.platform()), protocol.name()));
+        }
+    }
+
+    let platform = reply.strip_prefix(DEBUG_REPLY_PREFIX)?;
+    let platform = std::str::from_utf8(platform).ok();
+    Some((platform.and_then(|p| p.trim().parse().ok()), DEBUG_SERVICE))
+}
+
+/// Broadcasts the discovery pings for `timeout` and collects whoever answers
+pub fn discover(debug_port: u16, timeout: Duration) -> Result<Vec<Device>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+
+    let mut pings = vec![(DEBUG_PING, debug_port)];
+    for protocol in LINK_PROTOCOLS {
+        if let Some((ping, _)) = protocol.discovery() {
+            pings.push((ping, protocol.port()));
+        }
+    }
+
+    let mut devices = BTreeMap::new();
+    let mut buffer = [0; 64];
+    let deadline = Instant::now() + timeout;
+    let mut next_ping = Instant::now();
+
+    while Instant::now() < deadline {
+        if Instant::now() >= next_ping {
+            for (ping, port) in &pings {
+                socket.send_to(ping, (Ipv4Addr::BROADCAST, *port))?;
+            }
+            next_ping += PING_INTERVAL;
+        }
+
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let SocketAddr::V4(from) = from else {
+            continue;
+        };
+
+        if let Some((platform, service)) = identify(&buffer[..length]) {
+            let device = devices.entry(*from.ip()).or_insert_with(|| Device {
+                address: *from.ip(),
+                platform: None,
+                services: Vec::new(),
+            });
+            device.platform = device.platform.or(platform);
+            if !device.services.contains(&service) {
+                device.services.push(service);
+            }
+        }
+    }
+    Ok(devices.into_values().collect())
+}
@@ -0,0 +1,152 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use sha2::{Digest, Sha256};
+
+use crate::config::bundle::PlatformTarget;
+
+/// What each console's home menu expects an icon to be.
+pub struct IconSpec {
+    pub device: &'static str,
+    pub size: u32,
+    pub format: ImageFormat,
+}
+
+impl IconSpec {
+    pub fn for_platform(platform: PlatformTarget) -> Self {
+        match platform {
+            PlatformTarget::Ctr => Self {
+                device: "Nintendo 3DS",
+                size: 48,
+                format: ImageFormat::Png,
+            },
+            PlatformTarget::Hac => Self {
+                device: "Nintendo Switch",
+                size: 256,
+                format: ImageFormat::Jpeg,
+            },
+            PlatformTarget::Cafe => Self {
+                device: "Nintendo Wiiᵘ",
+                size: 128,
+                format: ImageFormat::Png,
+            },
+        }
+    }
+
+    fn format_name(&self) -> &'static str {
+        match self.format {
+            ImageFormat::Jpeg => "JPEG",
+            _ => "PNG",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self.format {
+            ImageFormat::Jpeg => "jpg",
+            _ => "png",
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<(Option<ImageFormat>, DynamicImage)> {
+    if !path.is_file() {
+        bail!("{path:#?} not found");
+    }
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    match reader.decode() {
+        Ok(image) => Ok((format, image)),
+        Err(e) => bail!("{path:#?} could not be read: {e}"),
+    }
+}
+
+/// Loads an icon, failing unless it is exactly what `platform` expects
+pub fn load(platform: PlatformTarget, path: &Path) -> Result<RgbaImage> {
+    let spec = IconSpec::for_platform(platform);
+    let (format, image) = open(path)?;
+
+    let found = match format {
+        Some(format) => format!("{format:?}").to_uppercase(),
+        None => String::from("unknown"),
+    };
+    if format != Some(spec.format) || image.width() != spec.size || image.height() != spec.size {
+        bail!(
+            "{} icon {path:#?} must be a {}x{} {}, found a {}x{} {found}",
+            spec.device,
+            spec.size,
+            spec.size,
+            spec.format_name(),
+            image.width(),
+            image.height(),
+        );
+    }
+    Ok(image.to_rgba8())
+}
+
+/// Where the conversion of `source` for `platform` is kept in `cache`
+fn converted_path(platform: PlatformTarget, source: &Path, cache: &Path) -> Result<PathBuf> {
+    let spec = IconSpec::for_platform(platform);
+    let digest = Sha256::new()
+        .chain_update(std::fs::read(source)?)
+        .chain_update(spec.size.to_le_bytes())
+        .finalize();
+    let hash: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    Ok(cache.join(format!("{platform}-{hash}.{}", spec.extension())))
+}
+
+/// Loads an image to convert, failing if it is too small for `platform`
+fn open_source(platform: PlatformTarget, source: &Path) -> Result<DynamicImage> {
+    let spec = IconSpec::for_platform(platform);
+    let (_, image) = open(source)?;
+    if image.width() < spec.size || image.height() < spec.size {
+        bail!(
+            "{source:#?} is {}x{}, too small for the {}x{} {} icon",
+            image.width(),
+            image.height(),
+            spec.size,
+            spec.size,
+            spec.device
+        );
+    }
+    Ok(image)
+}
+
+/// Checks that `source` can be converted for `platform`, without writing
+/// anything, returning where [`convert`] would put it
+pub fn check(platform: PlatformTarget, source: &Path, cache: &Path) -> Result<PathBuf> {
+    let path = converted_path(platform, source, cache)?;
+    open_source(platform, source)?;
+    Ok(path)
+}
+
+/// Resizes `source` to what `platform` expects, reusing earlier conversions
+/// kept in `cache` while the source is unchanged
+pub fn convert(platform: PlatformTarget, source: &Path, cache: &Path) -> Result<PathBuf> {
+    let spec = IconSpec::for_platform(platform);
+    let path = converted_path(platform, source, cache)?;
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let image = open_source(platform, source)?;
+    // crop to the centre rather than stretch non-square sources
+    let resized = image.resize_to_fill(spec.size, spec.size, FilterType::Lanczos3);
+    std::fs::create_dir_all(cache)?;
+    // conversions of an older source are never used again
+    for entry in std::fs::read_dir(cache)?.filter_map(|entry| entry.ok()) {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&format!("{platform}-"))
+        {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    match spec.format {
+        ImageFormat::Jpeg => resized.to_rgb8().save_with_format(&path, spec.format)?,
+        _ => resized.to_rgba8().save_with_format(&path, spec.format)?,
+    }
+    Ok(path)
+}
@@ -0,0 +1,196 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream};
+
+use anyhow::{Result, bail};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use indicatif::ProgressBar;
+
+use crate::config::bundle::PlatformTarget;
+
+/// The homebrew launchers only accept compressed chunks up to this size
+const ZLIB_CHUNK: usize = 0x4000;
+
+const WIILOAD_MAGIC: &[u8] = b"HAXX";
+const WIILOAD_VERSION: [u8; 2] = [0, 5];
+
+/// Tells libnx to redirect stdio to the host that sent the file
+pub const NXLINK_SERVER_ARG: &str = "_NXLINK_";
+pub const NXLINK_CLIENT_PORT: u16 = 28771;
+
+/// The network loaders of the homebrew launchers on each console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    ThreedsLink,
+    NxLink,
+    Wiiload,
+}
+
+impl Protocol {
+    pub fn for_platform(platform: PlatformTarget) -> Self {
+        match platform {
+            PlatformTarget::Ctr => Self::ThreedsLink,
+            PlatformTarget::Hac => Self::NxLink,
+            PlatformTarget::Cafe => Self::Wiiload,
+        }
+    }
+
+    pub fn platform(&self) -> PlatformTarget {
+        match self {
+            Self::ThreedsLink => PlatformTarget::Ctr,
+            Self::NxLink => PlatformTarget::Hac,
+            Self::Wiiload => PlatformTarget::Cafe,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ThreedsLink => "3dslink",
+            Self::NxLink => "nxlink",
+            Self::Wiiload => "wiiload",
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        match self {
+            Self::ThreedsLink => 17491,
+            Self::NxLink => 28280,
+            Self::Wiiload => 4299,
+        }
+    }
+
+    /// The UDP broadcast sent to find consoles and the reply they answer with
+    pub fn discovery(&self) -> Option<(&'static [u8], &'static [u8])> {
+        match self {
+            Self::ThreedsLink => Some((b"3dsboot", b"boot3ds")),
+            Self::NxLink => Some((b"nxboot", b"bootnx")),
+            Self::Wiiload => None,
+        }
+    }
+
+    fn argv0(&self, name: &str) -> String {
+        match self {
+            Self::ThreedsLink => format!("3dslink:/{name}"),
+            Self::NxLink => format!("sdmc:/switch/{name}"),
+            Self::Wiiload => name.to_string(),
+        }
+    }
+}
+
+/// A file to be pushed to and launched on a console.
+pub struct Upload<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+    pub args: &'a [String],
+}
+
+impl Upload<'_> {
+    /// argv as a list of NUL-terminated strings, starting with the launch path
+    fn command_line(&self, protocol: Protocol) -> Vec<u8> {
+        let mut command = Vec::new();
+        let argv0 = protocol.argv0(self.name);
+        for arg in std::iter::once(&argv0).chain(self.args) {
+            command.extend_from_slice(arg.as_bytes());
+            command.push(0);
+        }
+        command
+    }
+}
+
+fn write_i32(stream: &mut TcpStream, value: usize) -> Result<()> {
+    let value = i32::try_from(value)?;
+    stream.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_i32(stream: &mut TcpStream) -> Result<i32> {
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer)?;
+    Ok(i32::from_le_bytes(buffer))
+}
+
+fn write_chunks(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    for chunk in data.chunks(ZLIB_CHUNK) {
+        write_i32(stream, chunk.len())?;
+        stream.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// 3dslink and nxlink share the same protocol, only the port and paths differ
+fn send_link(
+    stream: &mut TcpStream,
+    protocol: Protocol,
+    upload: &Upload,
+    progress: &ProgressBar,
+) -> Result<()> {
+    write_i32(stream, upload.name.len())?;
+    stream.write_all(upload.name.as_bytes())?;
+    write_i32(stream, upload.data.len())?;
+
+    match read_i32(stream)? {
+        0 => {}
+        -1 => bail!("The console failed to create the file"),
+        -2 => bail!("The console has insufficient space"),
+        -3 => bail!("The console has insufficient memory"),
+        code => bail!("The console refused the file ({code})"),
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for chunk in upload.data.chunks(ZLIB_CHUNK) {
+        encoder.write_all(chunk)?;
+        let compressed = std::mem::take(encoder.get_mut());
+        write_chunks(stream, &compressed)?;
+        progress.inc(chunk.len() as u64);
+    }
+    write_chunks(stream, &encoder.finish()?)?;
+
+    // the console acknowledges the file before it takes the arguments
+    read_i32(stream)?;
+    let command = upload.command_line(protocol);
+    write_i32(stream, command.len())?;
+    stream.write_all(&command)?;
+    Ok(())
+}
+
+fn send_wiiload(stream: &mut TcpStream, upload: &Upload, progress: &ProgressBar) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(upload.data)?;
+    let compressed = encoder.finish()?;
+    let command = upload.command_line(Protocol::Wiiload);
+
+    stream.write_all(WIILOAD_MAGIC)?;
+    stream.write_all(&WIILOAD_VERSION)?;
+    stream.write_all(&u16::try_from(command.len())?.to_be_bytes())?;
+    stream.write_all(&u32::try_from(compressed.len())?.to_be_bytes())?;
+    stream.write_all(&u32::try_from(upload.data.len())?.to_be_bytes())?;
+
+    let ratio = upload.data.len() as f64 / compressed.len().max(1) as f64;
+    for chunk in compressed.chunks(ZLIB_CHUNK) {
+        stream.write_all(chunk)?;
+        progress.inc((chunk.len() as f64 * ratio) as u64);
+    }
+    stream.write_all(&command)?;
+    Ok(())
+}
+
+pub fn send(
+    protocol: Protocol,
+    address: IpAddr,
+    upload: &Upload,
+    progress: &ProgressBar,
+) -> Result<()> {
+    let mut stream = match TcpStream::connect((address, protocol.port())) {
+        Ok(stream) => stream,
+        Err(e) => bail!("Failed to connect to {address}:{}: {e}", protocol.port()),
+    };
+
+    match protocol {
+        Protocol::ThreedsLink | Protocol::NxLink => {
+            send_link(&mut stream, protocol, upload, progress)?
+        }
+        Protocol::Wiiload => send_wiiload(&mut stream, upload, progress)?,
+    }
+    progress.finish();
+    Ok(())
+}
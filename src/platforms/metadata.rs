@@ -0,0 +1,308 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::RgbaImage;
+use image::imageops::FilterType;
+
+use crate::config::bundle::{Metadata, PlatformTarget};
+use crate::platforms::icon;
+
+const SMDH_MAGIC: &[u8] = b"SMDH";
+const SMDH_SIZE: usize = 0x36C0;
+const SMDH_TITLES: usize = 0x08;
+const SMDH_TITLE_SIZE: usize = 0x200;
+const SMDH_SETTINGS: usize = 0x2008;
+const SMDH_SMALL_ICON: usize = 0x2040;
+const SMDH_LARGE_ICON: usize = 0x24C0;
+const SMDH_REGION_FREE: u32 = 0x7FFF_FFFF;
+/// Visible on the HOME Menu, 3D allowed, and play time recorded
+const SMDH_FLAGS: u32 = 0x0001 | 0x0004 | 0x0100;
+
+const NACP_SIZE: usize = 0x4000;
+const NACP_TITLE_SIZE: usize = 0x300;
+const NACP_NAME_SIZE: usize = 0x200;
+const NACP_PUBLISHER_SIZE: usize = 0x100;
+const NACP_DISPLAY_VERSION: usize = 0x3060;
+const NACP_DISPLAY_VERSION_SIZE: usize = 0x10;
+
+/// Every language slot gets the same strings
+const LANGUAGES: usize = 16;
+
+const CTR_SMALL_ICON_SIZE: u32 = 24;
+
+/// Order of the pixels inside each 8x8 tile of a 3DS texture
+const TILE_ORDER: [u8; 64] = [
+    0, 1, 8, 9, 2, 3, 10, 11, 16, 17, 24, 25, 18, 19, 26, 27, 4, 5, 12, 13, 6, 7, 14, 15, 20, 21,
+    28, 29, 22, 23, 30, 31, 32, 33, 40, 41, 34, 35, 42, 43, 48, 49, 56, 57, 50, 51, 58, 59, 36, 37,
+    44, 45, 38, 39, 46, 47, 52, 53, 60, 61, 54, 55, 62, 63,
+];
+
+/// Writes `text` as UTF-16 into `buffer`, truncated to leave a terminator
+fn write_utf16(buffer: &mut [u8], text: &str) {
+    let units = text.encode_utf16().take(buffer.len() / 2 - 1);
+    for (index, unit) in units.enumerate() {
+        buffer[index * 2..index * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+}
+
+/// Writes `text` as UTF-8 into `buffer`, truncated on a character boundary
+fn write_utf8(buffer: &mut [u8], text: &str) {
+    let mut end = text.len().min(buffer.len() - 1);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    buffer[..end].copy_from_slice(&text.as_bytes()[..end]);
+}
+
+/// Transparent pixels are blended onto black, the format has no alpha
+fn rgb565(pixel: &image::Rgba<u8>) -> u16 {
+    let [r, g, b, a] = pixel.0.map(u16::from);
+    let (r, g, b) = (r * a / 255, g * a / 255, b * a / 255);
+    ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3)
+}
+
+fn tiled_rgb565(image: &RgbaImage) -> Vec<u8> {
+    let mut data = Vec::with_capacity((image.width() * image.height() * 2) as usize);
+    for tile_y in (0..image.height()).step_by(8) {
+        for tile_x in (0..image.width()).step_by(8) {
+            for index in TILE_ORDER {
+                let x = tile_x + u32::from(index & 7);
+                let y = tile_y + u32::from(index >> 3);
+                data.extend_from_slice(&rgb565(image.get_pixel(x, y)).to_le_bytes());
+            }
+        }
+    }
+    data
+}
+
+/// Builds the 3DS SMDH from the metadata and a 48x48 PNG icon
+pub fn smdh(metadata: &Metadata, icon: &Path) -> Result<Vec<u8>> {
+    let large = icon::load(PlatformTarget::Ctr, icon)?;
+    let small = image::imageops::resize(
+        &large,
+        CTR_SMALL_ICON_SIZE,
+        CTR_SMALL_ICON_SIZE,
+        FilterType::Lanczos3,
+    );
+
+    let mut data = vec![0; SMDH_SIZE];
+    data[..4].copy_from_slice(SMDH_MAGIC);
+
+    for language in 0..LANGUAGES {
+        let title = SMDH_TITLES + language * SMDH_TITLE_SIZE;
+        write_utf16(&mut data[title..title + 0x80], &metadata.name);
+        write_utf16(
+            &mut data[title + 0x80..title + 0x180],
+            &metadata.description,
+        );
+        write_utf16(&mut data[title + 0x180..title + 0x200], &metadata.author);
+    }
+
+    let settings = SMDH_SETTINGS;
+    data[settings + 0x10..settings + 0x14].copy_from_slice(&SMDH_REGION_FREE.to_le_bytes());
+    data[settings + 0x20..settings + 0x24].copy_from_slice(&SMDH_FLAGS.to_le_bytes());
+
+    let small = tiled_rgb565(&small);
+    let large = tiled_rgb565(&large);
+    data[SMDH_SMALL_ICON..SMDH_SMALL_ICON + small.len()].copy_from_slice(&small);
+    data[SMDH_LARGE_ICON..SMDH_LARGE_ICON + large.len()].copy_from_slice(&large);
+    Ok(data)
+}
+
+/// Builds the Switch NACP from the metadata
+pub fn nacp(metadata: &Metadata) -> Vec<u8> {
+    let mut data = vec![0; NACP_SIZE];
+
+    for language in 0..LANGUAGES {
+        let title = language * NACP_TITLE_SIZE;
+        let publisher = title + NACP_NAME_SIZE;
+        write_utf8(&mut data[title..publisher], &metadata.name);
+        write_utf8(
+            &mut data[publisher..publisher + NACP_PUBLISHER_SIZE],
+            &metadata.author,
+        );
+    }
+
+    let version = NACP_DISPLAY_VERSION;
+    write_utf8(
+        &mut data[version..version + NACP_DISPLAY_VERSION_SIZE],
+        &metadata.version,
+    );
+    data
+}
+
+/// The Switch icon is embedded as-is, so it has to be a 256x256 JPEG already
+pub fn hac_icon(icon: &Path) -> Result<Vec<u8>> {
+    icon::load(PlatformTarget::Hac, icon)?;
+    Ok(std::fs::read(icon)?)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The homebrew launcher's description of a Wii U app
+pub fn meta_xml(metadata: &Metadata) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <app version=\"1\">\n  \
+         <name>{}</name>\n  \
+         <coder>{}</coder>\n  \
+         <version>{}</version>\n  \
+         <short_description>{}</short_description>\n  \
+         <long_description>{}</long_description>\n\
+         </app>\n",
+        escape_xml(&metadata.name),
+        escape_xml(&metadata.author),
+        escape_xml(&metadata.version),
+        escape_xml(&metadata.description),
+        escape_xml(&metadata.description),
+    )
+}
+
+/// Converts a 128x128 PNG icon to the uncompressed 32-bit TGA the Wii U uses
+pub fn cafe_icon(icon: &Path) -> Result<Vec<u8>> {
+    let image = icon::load(PlatformTarget::Cafe, icon)?;
+    let (width, height) = (image.width() as u16, image.height() as u16);
+
+    let mut data = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+    // 32 bits per pixel, 8 of them alpha, rows stored top to bottom
+    data.extend_from_slice(&[32, 0x28]);
+
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        data.extend_from_slice(&[b, g, r, a]);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            name: String::from("Game"),
+            author: String::from("Author"),
+            description: String::from("A game"),
+            version: String::from("1.2.3"),
+            icon: None,
+            icons: HashMap::new(),
+        }
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// An image whose pixels encode their coordinates once in RGB565
+    fn coordinates(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 8) as u8, (y * 4) as u8, 0, 255])
+        })
+    }
+
+    fn decode(data: &[u8]) -> Vec<(u16, u16)> {
+        data.chunks(2)
+            .map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]]))
+            .map(|pixel| (pixel >> 11, (pixel >> 5) & 0x3F))
+            .collect()
+    }
+
+    #[test]
+    fn tiles_rgb565_in_morton_order() {
+        let pixels = decode(&tiled_rgb565(&coordinates(16, 8)));
+        assert_eq!(pixels.len(), 128);
+        assert_eq!(
+            pixels[..8],
+            [
+                (0, 0),
+                (1, 0),
+                (0, 1),
+                (1, 1),
+                (2, 0),
+                (3, 0),
+                (2, 1),
+                (3, 1)
+            ]
+        );
+        assert_eq!(pixels[16..20], [(4, 0), (5, 0), (4, 1), (5, 1)]);
+        assert_eq!(pixels[32], (0, 4));
+        assert_eq!(pixels[63], (7, 7));
+        assert_eq!(pixels[64..66], [(8, 0), (9, 0)]);
+    }
+
+    #[test]
+    fn blends_transparency_onto_black() {
+        assert_eq!(rgb565(&image::Rgba([255, 255, 255, 255])), 0xFFFF);
+        assert_eq!(rgb565(&image::Rgba([255, 255, 255, 0])), 0);
+        assert_eq!(rgb565(&image::Rgba([255, 0, 0, 255])), 0xF800);
+    }
+
+    #[test]
+    fn writes_the_smdh_layout() {
+        let icon = std::env::temp_dir().join(format!("nestcli-{}-smdh.png", std::process::id()));
+        coordinates(48, 48).save(&icon).unwrap();
+        let data = smdh(&metadata(), &icon);
+        std::fs::remove_file(&icon).unwrap();
+        let data = data.unwrap();
+
+        assert_eq!(data.len(), SMDH_SIZE);
+        assert_eq!(&data[..4], SMDH_MAGIC);
+        for language in [0, LANGUAGES - 1] {
+            let title = SMDH_TITLES + language * SMDH_TITLE_SIZE;
+            assert_eq!(data[title..title + 10], utf16("Game\0"));
+            assert_eq!(data[title + 0x80..title + 0x8E], utf16("A game\0"));
+            assert_eq!(data[title + 0x180..title + 0x18E], utf16("Author\0"));
+        }
+        assert_eq!(data[0x2018..0x201C], [0xFF, 0xFF, 0xFF, 0x7F]);
+        assert_eq!(data[0x2028..0x202C], [0x05, 0x01, 0, 0]);
+
+        let large = decode(&data[SMDH_LARGE_ICON..SMDH_SIZE]);
+        assert_eq!(large.len(), 48 * 48);
+        assert_eq!(large[..4], [(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(large[64], (8, 0));
+        assert_eq!(large[64 * 6], (0, 8));
+    }
+
+    #[test]
+    fn writes_the_nacp_layout() {
+        let mut metadata = metadata();
+        metadata.name = "é".repeat(NACP_NAME_SIZE);
+        let data = nacp(&metadata);
+
+        assert_eq!(data.len(), NACP_SIZE);
+        for language in [0, LANGUAGES - 1] {
+            let title = language * NACP_TITLE_SIZE;
+            // truncated to a whole character, leaving a terminator
+            assert_eq!(
+                data[title + NACP_NAME_SIZE - 2..title + NACP_NAME_SIZE],
+                [0, 0]
+            );
+            assert!(std::str::from_utf8(&data[title..title + NACP_NAME_SIZE - 2]).is_ok());
+            assert_eq!(data[title + NACP_NAME_SIZE..title + 0x207], *b"Author\0");
+        }
+        assert_eq!(data[NACP_DISPLAY_VERSION..0x3066], *b"1.2.3\0");
+    }
+
+    #[test]
+    fn escapes_the_meta_xml() {
+        let mut metadata = metadata();
+        metadata.name = String::from("Cats & <Dogs>");
+        let xml = meta_xml(&metadata);
+
+        assert!(xml.starts_with("<?xml version=\"1.0\""));
+        assert!(xml.contains("  <name>Cats &amp; &lt;Dogs&gt;</name>\n"));
+        assert!(xml.contains("  <coder>Author</coder>\n"));
+        assert!(xml.contains("  <version>1.2.3</version>\n"));
+        assert!(xml.contains("  <long_description>A game</long_description>\n"));
+        assert!(xml.ends_with("</app>\n"));
+    }
+}
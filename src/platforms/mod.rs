@@ -1 +1,12 @@
 pub mod addr2line;
+pub mod annotate;
+pub mod bytecode;
+pub mod detect;
+pub mod devkitpro;
+pub mod discover;
+pub mod icon;
+pub mod link;
+pub mod metadata;
+pub mod package;
+pub mod symbolizer;
+pub mod texture;
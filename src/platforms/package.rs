@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::devkitpro::run_tool;
+
+const THREEDSX_MAGIC: &[u8] = b"3DSX";
+/// Size of the 3DSX header once the SMDH and RomFS offsets are included
+const THREEDSX_EXTENDED_HEADER: usize = 0x2C;
+const THREEDSX_BASE_HEADER: usize = 0x20;
+
+const NRO_MAGIC: &[u8] = b"NRO0";
+const NRO_SIZE_OFFSET: usize = 0x18;
+const ASET_MAGIC: &[u8] = b"ASET";
+const ASET_HEADER: usize = 0x38;
+
+/// The LÖVE Potion runtime each target is packaged from.
+pub fn runtime_name(platform: PlatformTarget) -> &'static str {
+    match platform {
+        PlatformTarget::Ctr => "lovepotion.3dsx",
+        PlatformTarget::Hac => "lovepotion.nro",
+        PlatformTarget::Cafe => "lovepotion.rpx",
+    }
+}
+
+pub fn extension(platform: PlatformTarget) -> &'static str {
+    match platform {
+        PlatformTarget::Ctr => "3dsx",
+        PlatformTarget::Hac => "nro",
+        PlatformTarget::Cafe => "wuhb",
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into()?)),
+        None => bail!("Unexpected end of file at 0x{offset:x}"),
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    match data.get(offset..offset + 8) {
+        Some(bytes) => Ok(u64::from_le_bytes(bytes.try_into()?)),
+        None => bail!("Unexpected end of file at 0x{offset:x}"),
+    }
+}
+
+fn slice(data: &[u8], offset: usize, size: usize) -> Result<&[u8]> {
+    match data.get(offset..offset + size) {
+        Some(bytes) => Ok(bytes),
+        None => bail!("Section at 0x{offset:x} runs past the end of the file"),
+    }
+}
+
+/// End of the code, data and relocations of a 3DSX, where the SMDH starts
+fn threedsx_executable_end(runtime: &[u8]) -> Result<usize> {
+    let header_size = u16::from_le_bytes([runtime[4], runtime[5]]) as usize;
+    let reloc_header_size = u16::from_le_bytes([runtime[6], runtime[7]]) as usize;
+
+    let code = read_u32(runtime, 0x10)? as usize;
+    let rodata = read_u32(runtime, 0x14)? as usize;
+    let data = read_u32(runtime, 0x18)? as usize;
+    let bss = read_u32(runtime, 0x1C)? as usize;
+
+    // one relocation header per segment, each reloc is a pair of u16s
+    let mut relocations = 0;
+    for segment in 0..3 {
+        let offset = header_size + segment * reloc_header_size;
+        relocations +=
+            read_u32(runtime, offset)? as usize + read_u32(runtime, offset + 4)? as usize;
+    }
+
+    let end = header_size
+        + reloc_header_size * 3
+        + code
+        + rodata
+        + data.saturating_sub(bss)
+        + relocations * 4;
+    if end > runtime.len() {
+        bail!("The 3DSX runtime is truncated");
+    }
+    Ok(end)
+}
+
+/// Rebuilds the runtime 3DSX with `smdh`, keeping its RomFS, and appends the game
+pub fn fuse_3dsx(runtime: &[u8], smdh: &[u8], game: &[u8]) -> Result<Vec<u8>> {
+    if runtime.len() < THREEDSX_BASE_HEADER || !runtime.starts_with(THREEDSX_MAGIC) {
+        bail!("The 3DS runtime is not a 3DSX");
+    }
+
+    let header_size = u16::from_le_bytes([runtime[4], runtime[5]]) as usize;
+    if header_size < THREEDSX_EXTENDED_HEADER {
+        bail!("The 3DS runtime has no RomFS");
+    }
+    let romfs = match runtime.get(read_u32(runtime, 0x28)? as usize..) {
+        Some(romfs) => romfs,
+        None => bail!("The 3DS runtime has an invalid RomFS offset"),
+    };
+    let executable = &runtime[header_size..threedsx_executable_end(runtime)?];
+
+    let smdh_offset = THREEDSX_EXTENDED_HEADER + executable.len();
+    let romfs_offset = smdh_offset + smdh.len();
+
+    let mut output = Vec::with_capacity(romfs_offset + romfs.len() + game.len());
+    output.extend_from_slice(&runtime[..4]);
+    output.extend_from_slice(&(THREEDSX_EXTENDED_HEADER as u16).to_le_bytes());
+    output.extend_from_slice(&runtime[6..THREEDSX_BASE_HEADER]);
+    output.extend_from_slice(&u32::try_from(smdh_offset)?.to_le_bytes());
+    output.extend_from_slice(&u32::try_from(smdh.len())?.to_le_bytes());
+    output.extend_from_slice(&u32::try_from(romfs_offset)?.to_le_bytes());
+    output.extend_from_slice(executable);
+    output.extend_from_slice(smdh);
+    output.extend_from_slice(romfs);
+    output.extend_from_slice(game);
+    Ok(output)
+}
+
+/// Rebuilds the assets of the runtime NRO with `icon` and `nacp`, keeping its
+/// RomFS, and appends the game
+pub fn fuse_nro(runtime: &[u8], icon: &[u8], nacp: &[u8], game: &[u8]) -> Result<Vec<u8>> {
+    if runtime.get(0x10..0x14) != Some(NRO_MAGIC) {
+        bail!("The Switch runtime is not an NRO");
+    }
+
+    let nro_size = read_u32(runtime, NRO_SIZE_OFFSET)? as usize;
+    let assets = match runtime.get(nro_size..) {
+        Some(assets) if assets.starts_with(ASET_MAGIC) => assets,
+        _ => bail!("The Switch runtime has no asset section"),
+    };
+    let romfs_offset = read_u64(assets, 0x28)? as usize;
+    let romfs_size = read_u64(assets, 0x30)? as usize;
+    let romfs = slice(assets, romfs_offset, romfs_size)?;
+
+    let icon_offset = ASET_HEADER;
+    let nacp_offset = icon_offset + icon.len();
+    let romfs_offset = nacp_offset + nacp.len();
+
+    let mut output = Vec::with_capacity(nro_size + romfs_offset + romfs.len() + game.len());
+    output.extend_from_slice(&runtime[..nro_size]);
+    output.extend_from_slice(ASET_MAGIC);
+    output.extend_from_slice(&0u32.to_le_bytes());
+    for (offset, size) in [
+        (icon_offset, icon.len()),
+        (nacp_offset, nacp.len()),
+        (romfs_offset, romfs.len()),
+    ] {
+        output.extend_from_slice(&(offset as u64).to_le_bytes());
+        output.extend_from_slice(&(size as u64).to_le_bytes());
+    }
+    output.extend_from_slice(icon);
+    output.extend_from_slice(nacp);
+    output.extend_from_slice(romfs);
+    output.extend_from_slice(game);
+    Ok(output)
+}
+
+/// Wraps the runtime RPX in a WUHB with `content` mounted as its content folder
+pub fn build_wuhb(
+    runtime: &Path,
+    content: &Path,
+    output: &Path,
+    name: &str,
+    author: &str,
+    icon: &Path,
+) -> Result<()> {
+    let short_name = format!("--short-name={name}");
+    let name = format!("--name={name}");
+    let author = format!("--author={author}");
+    let icon = format!("--icon={}", icon.display());
+    let content = format!("--content={}", content.display());
+
+    run_tool(
+        "wuhbtool",
+        &[
+            runtime.as_os_str(),
+            output.as_os_str(),
+            content.as_ref(),
+            name.as_ref(),
+            short_name.as_ref(),
+            author.as_ref(),
+            icon.as_ref(),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    /// A 3DSX with 4 bytes each of code, rodata and data, 4 of them bss,
+    /// one relocation, an SMDH and a RomFS
+    fn threedsx() -> Vec<u8> {
+        let mut runtime = THREEDSX_MAGIC.to_vec();
+        runtime.extend_from_slice(&(THREEDSX_EXTENDED_HEADER as u16).to_le_bytes());
+        runtime.extend_from_slice(&8u16.to_le_bytes());
+        runtime.extend_from_slice(&[0xAA; 8]);
+        for size in [4u32, 4, 8, 4] {
+            runtime.extend_from_slice(&size.to_le_bytes());
+        }
+        // SMDH offset and size, then the RomFS offset
+        for value in [0x54u32, 4, 0x58] {
+            runtime.extend_from_slice(&value.to_le_bytes());
+        }
+        for (absolute, relative) in [(1u32, 0u32), (0, 0), (0, 0)] {
+            runtime.extend_from_slice(&absolute.to_le_bytes());
+            runtime.extend_from_slice(&relative.to_le_bytes());
+        }
+        runtime.extend_from_slice(b"codeconsdatarelo");
+        assert_eq!(runtime.len(), 0x54);
+        runtime.extend_from_slice(b"SMDH");
+        runtime.extend_from_slice(b"romfs");
+        runtime
+    }
+
+    #[test]
+    fn fuses_a_3dsx() {
+        let runtime = threedsx();
+        let output = fuse_3dsx(&runtime, b"new smdh", b"game").unwrap();
+
+        assert_eq!(&output[..4], THREEDSX_MAGIC);
+        assert_eq!(u16_at(&output, 4) as usize, THREEDSX_EXTENDED_HEADER);
+        assert_eq!(&output[6..THREEDSX_BASE_HEADER], &runtime[6..0x20]);
+        assert_eq!(read_u32(&output, 0x20).unwrap(), 0x54);
+        assert_eq!(read_u32(&output, 0x24).unwrap(), 8);
+        assert_eq!(read_u32(&output, 0x28).unwrap(), 0x5C);
+        assert_eq!(&output[0x2C..0x54], &runtime[0x2C..0x54]);
+        assert_eq!(&output[0x54..0x5C], b"new smdh");
+        assert_eq!(&output[0x5C..], b"romfsgame");
+    }
+
+    #[test]
+    fn rejects_3dsx_without_a_romfs() {
+        let mut runtime = threedsx();
+        assert!(fuse_3dsx(&runtime[..0x30], b"", b"").is_err());
+
+        runtime[4..6].copy_from_slice(&(THREEDSX_BASE_HEADER as u16).to_le_bytes());
+        assert!(fuse_3dsx(&runtime, b"", b"").is_err());
+
+        runtime[..4].copy_from_slice(b"NRO0");
+        assert!(fuse_3dsx(&runtime, b"", b"").is_err());
+    }
+
+    /// A 0x20 byte NRO followed by assets with an icon, a NACP and a RomFS
+    fn nro() -> Vec<u8> {
+        let mut runtime = vec![0xBB; 0x20];
+        runtime[0x10..0x14].copy_from_slice(NRO_MAGIC);
+        runtime[NRO_SIZE_OFFSET..NRO_SIZE_OFFSET + 4].copy_from_slice(&0x20u32.to_le_bytes());
+
+        runtime.extend_from_slice(ASET_MAGIC);
+        runtime.extend_from_slice(&0u32.to_le_bytes());
+        for value in [0x38u64, 4, 0x3C, 4, 0x40, 5] {
+            runtime.extend_from_slice(&value.to_le_bytes());
+        }
+        runtime.extend_from_slice(b"iconnacpromfs");
+        runtime
+    }
+
+    #[test]
+    fn fuses_an_nro() {
+        let runtime = nro();
+        let output = fuse_nro(&runtime, b"new icon", b"nacp!", b"game").unwrap();
+
+        assert_eq!(&output[..0x20], &runtime[..0x20]);
+        let assets = &output[0x20..];
+        assert_eq!(&assets[..4], ASET_MAGIC);
+        let sections: Vec<u64> = (0..6)
+            .map(|index| read_u64(assets, 8 + index * 8).unwrap())
+            .collect();
+        assert_eq!(sections, [0x38, 8, 0x40, 5, 0x45, 5]);
+        assert_eq!(&assets[ASET_HEADER..], b"new iconnacp!romfsgame");
+    }
+
+    #[test]
+    fn rejects_nro_without_assets() {
+        let runtime = nro();
+        assert!(fuse_nro(&runtime[..0x20], b"", b"", b"").is_err());
+        assert!(fuse_nro(&runtime[..0x60], b"", b"", b"").is_err());
+        assert!(fuse_nro(&runtime[0x10..], b"", b"", b"").is_err());
+    }
+}
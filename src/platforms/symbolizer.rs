@@ -0,0 +1,81 @@
+use std::fmt;
+use std::path::Path;
+
+use addr2line::Loader;
+use anyhow::{Result, anyhow};
+
+/// A single resolved frame; inlined frames come before the function they were inlined into.
+pub struct Frame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = self.function.as_deref().unwrap_or("??");
+        let file = self.file.as_deref().unwrap_or("??");
+        write!(f, "{function} at {file}:{}", self.line.unwrap_or(0))
+    }
+}
+
+/// In-process DWARF and symbol table resolver for ARM, AArch64 and PowerPC ELFs.
+pub struct Symbolizer {
+    loader: Loader,
+}
+
+impl Symbolizer {
+    pub fn new(filepath: &Path) -> Result<Self> {
+        let loader = Loader::new(filepath)
+            .map_err(|e| anyhow!("Failed to load {}: {e}", filepath.display()))?;
+        Ok(Self { loader })
+    }
+
+    pub fn resolve(&self, address: u64) -> Result<Vec<Frame>> {
+        let mut frames = Vec::new();
+        let mut iter = self
+            .loader
+            .find_frames(address)
+            .map_err(|e| anyhow!("Failed to read debug info: {e}"))?;
+
+        while let Some(frame) = iter.next()? {
+            let function = match frame.function {
+                Some(name) => Some(name.demangle()?.into_owned()),
+                None => None,
+            };
+            let (file, line) = match frame.location {
+                Some(location) => (location.file.map(String::from), location.line),
+                None => (None, None),
+            };
+            frames.push(Frame {
+                function,
+                file,
+                line,
+            });
+        }
+
+        // no line tables for this address, fall back to the symbol table
+        if frames.iter().all(|frame| frame.function.is_none())
+            && let Some(symbol) = self.loader.find_symbol(address)
+        {
+            let function = addr2line::demangle_auto(symbol.into(), None).into_owned();
+            match frames.first_mut() {
+                Some(frame) => frame.function = Some(function),
+                None => frames.push(Frame {
+                    function: Some(function),
+                    file: None,
+                    line: None,
+                }),
+            }
+        }
+
+        if frames.is_empty() {
+            frames.push(Frame {
+                function: None,
+                file: None,
+                line: None,
+            });
+        }
+        Ok(frames)
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Result, bail};
+use image::{GenericImageView, ImageReader};
+use sha2::{Digest, Sha256};
+
+use crate::platforms::devkitpro::run_tool;
+
+/// The 3DS GPU can't sample textures larger than this
+const MAX_TEXTURE_SIZE: u32 = 1024;
+
+/// Tells apart the scratch files of conversions running at the same time
+static CONVERSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Resources LÖVE Potion on the 3DS can only load in its own formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    Texture,
+    Font,
+}
+
+impl Conversion {
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" | "jpg" | "jpeg" => Some(Self::Texture),
+            "ttf" | "otf" => Some(Self::Font),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Texture => "t3x",
+            Self::Font => "bcfnt",
+        }
+    }
+}
+
+fn convert_texture(path: &Path, output: &Path, scratch: &Path) -> Result<()> {
+    let image = match ImageReader::open(path)?.with_guessed_format()?.decode() {
+        Ok(image) => image,
+        Err(e) => bail!("{path:#?} could not be read: {e}"),
+    };
+    if image.width() > MAX_TEXTURE_SIZE || image.height() > MAX_TEXTURE_SIZE {
+        bail!(
+            "{path:#?} is {}x{}, textures on the 3DS are at most {MAX_TEXTURE_SIZE}x{MAX_TEXTURE_SIZE}",
+            image.width(),
+            image.height()
+        );
+    }
+
+    // opaque images don't need to pay for an alpha channel
+    let opaque = image.pixels().all(|(_, _, pixel)| pixel.0[3] == u8::MAX);
+    let format = if opaque { "rgb888" } else { "rgba8888" };
+
+    // tex3ds pads to a power of two itself, keeping the original size for
+    // LÖVE Potion to report, so the image is only re-encoded
+    image
+        .to_rgba8()
+        .save_with_format(scratch, image::ImageFormat::Png)?;
+    let result = run_tool(
+        "tex3ds",
+        &[
+            "-f".as_ref(),
+            format.as_ref(),
+            "-z".as_ref(),
+            "auto".as_ref(),
+            "-o".as_ref(),
+            output.as_os_str(),
+            scratch.as_os_str(),
+        ],
+    );
+    // the tool's error matters more than a leftover image
+    let removed = fs::remove_file(scratch);
+    result?;
+    Ok(removed?)
+}
+
+/// Converts a texture or font for the 3DS, reusing the result of an earlier
+/// conversion in `cache` when the contents are unchanged
+pub fn convert(conversion: Conversion, path: &Path, cache: &Path) -> Result<PathBuf> {
+    let digest = Sha256::digest(fs::read(path)?);
+    let hash: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+
+    let output = cache.join(format!("{hash}.{}", conversion.extension()));
+    if output.is_file() {
+        return Ok(output);
+    }
+
+    // files with the same contents may be converted at the same time, by
+    // this build or another one, the result only takes its name once complete
+    fs::create_dir_all(cache)?;
+    let conversion_id = CONVERSIONS.fetch_add(1, Ordering::Relaxed);
+    let scratch = format!("{hash}-{}-{conversion_id}", std::process::id());
+    let partial = cache.join(format!("{scratch}.{}", conversion.extension()));
+    let result = match conversion {
+        Conversion::Texture => {
+            convert_texture(path, &partial, &cache.join(format!("{scratch}.png")))
+        }
+        Conversion::Font => run_tool(
+            "mkbcfnt",
+            &["-o".as_ref(), partial.as_os_str(), path.as_os_str()],
+        ),
+    };
+    // a failed tool may have left part of its output behind
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(partial, &output)?;
+    Ok(output)
+}
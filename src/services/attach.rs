@@ -0,0 +1,341 @@
+use std::fs::{self, File};
+use std::io::{IsTerminal, Write, stdout};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
+use rustyline::ExternalPrinter;
+
+use crate::models::socket::{Decoder, Level, Message, Packet, Socket};
+use crate::models::traceback::{Frame, SourceMap};
+use crate::platforms::annotate::Annotator;
+use crate::services::console::Remote;
+
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Which structured messages to show; legacy text is never filtered.
+#[derive(Default)]
+pub struct Filter {
+    pub level: Option<Level>,
+    pub grep: Option<Regex>,
+    pub tags: Vec<String>,
+}
+
+impl Filter {
+    fn matches(&self, message: &Message) -> bool {
+        if self.level.is_some_and(|level| message.level < level) {
+            return false;
+        }
+        if !self.tags.is_empty() && !self.tags.contains(&message.tag) {
+            return false;
+        }
+        match &self.grep {
+            Some(grep) => grep.is_match(&message.payload),
+            None => true,
+        }
+    }
+}
+
+/// Writes the output of an attached target to the screen and the log file,
+/// symbolizing crash addresses and mapping tracebacks when configured.
+pub struct Output {
+    file: Option<File>,
+    annotator: Option<Annotator>,
+    sources: Option<SourceMap>,
+    context: usize,
+    filter: Filter,
+    terminal: bool,
+    printer: Option<Box<dyn ExternalPrinter + Send>>,
+    decoder: Decoder,
+    pending: Vec<u8>,
+}
+
+fn level_color(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "\x1b[2m",
+        Level::Debug => "\x1b[36m",
+        Level::Info => "\x1b[32m",
+        Level::Warn => "\x1b[33m",
+        Level::Error => "\x1b[31m",
+    }
+}
+
+fn message_prefix(message: &Message, color: bool) -> String {
+    let seconds = message.timestamp / 1000;
+    let millis = message.timestamp % 1000;
+    let level = match color {
+        true => format!(
+            "{}{:<5}\x1b[0m",
+            level_color(message.level),
+            message.level.name()
+        ),
+        false => format!("{:<5}", message.level.name()),
+    };
+
+    match message.tag.is_empty() {
+        true => format!("[{seconds:>5}.{millis:03}] {level} "),
+        false => format!("[{seconds:>5}.{millis:03}] {level} [{}] ", message.tag),
+    }
+}
+
+fn hyperlink(path: &Path, text: &str) -> String {
+    let mut url = path.to_string_lossy().replace('\\', "/");
+    if !url.starts_with('/') {
+        url.insert(0, '/');
+    }
+    format!("\x1b]8;;file://{url}\x1b\\{text}\x1b]8;;\x1b\\")
+}
+
+fn source_context(frame: &Frame, context: usize) -> Option<String> {
+    let contents = fs::read_to_string(&frame.path).ok()?;
+    let start = frame.line.saturating_sub(context + 1);
+
+    let mut lines = String::new();
+    for (index, text) in contents
+        .lines()
+        .enumerate()
+        .skip(start)
+        .take(context * 2 + 1)
+    {
+        let number = index + 1;
+        let marker = if number == frame.line { '>' } else { ' ' };
+        lines.push_str(&format!("    {marker} {number:>5} | {text}\n"));
+    }
+    Some(lines)
+}
+
+impl Output {
+    pub fn new(
+        file: Option<File>,
+        annotator: Option<Annotator>,
+        sources: Option<SourceMap>,
+        context: usize,
+        filter: Filter,
+    ) -> Self {
+        Self {
+            file,
+            annotator,
+            sources,
+            context,
+            filter,
+            terminal: stdout().is_terminal(),
+            printer: None,
+            decoder: Decoder::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Prints through the console so output doesn't clobber the prompt
+    pub fn set_printer(&mut self, printer: Box<dyn ExternalPrinter + Send>) {
+        self.printer = Some(printer);
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.printer.is_some()
+    }
+
+    fn emit(&mut self, screen: &[u8], log: &[u8]) -> Result<()> {
+        match self.printer.as_mut() {
+            Some(printer) => printer.print(String::from_utf8_lossy(screen).into_owned())?,
+            None => stdout().write_all(screen)?,
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(log)?;
+        }
+        Ok(())
+    }
+
+    fn map_sources(&self, sources: &SourceMap, line: &str) -> (String, String) {
+        let frames = sources.frames(line);
+        if frames.is_empty() {
+            return (line.to_string(), line.to_string());
+        }
+
+        let mut screen = String::new();
+        let mut log = String::new();
+        let mut last = 0;
+
+        for frame in &frames {
+            let text = format!("{}:{}", sources.display_path(frame).display(), frame.line);
+            screen.push_str(&line[last..frame.range.start]);
+            log.push_str(&line[last..frame.range.start]);
+            match self.terminal {
+                true => screen.push_str(&hyperlink(&frame.path, &text)),
+                false => screen.push_str(&text),
+            }
+            log.push_str(&text);
+            last = frame.range.end;
+        }
+        screen.push_str(&line[last..]);
+        log.push_str(&line[last..]);
+
+        if self.context > 0 {
+            for frame in &frames {
+                if let Some(context) = source_context(frame, self.context) {
+                    screen.push_str(&context);
+                }
+            }
+        }
+        (screen, log)
+    }
+
+    /// Symbolizes and maps a line, returning what goes to the screen and the log
+    fn render(&mut self, line: &str) -> (String, String) {
+        let mut line = line.to_string();
+        if let Some(annotator) = self.annotator.as_mut() {
+            line = annotator.annotate(&line);
+        }
+
+        match &self.sources {
+            Some(sources) => self.map_sources(sources, &line),
+            None => (line.clone(), line),
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> Result<()> {
+        let (screen, log) = self.render(&String::from_utf8_lossy(line));
+        self.emit(screen.as_bytes(), log.as_bytes())
+    }
+
+    fn write_text(&mut self, data: &[u8]) -> Result<()> {
+        if self.annotator.is_none() && self.sources.is_none() && !self.is_interactive() {
+            return self.emit(data, data);
+        }
+
+        self.pending.extend_from_slice(data);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<u8>>();
+            self.write_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn write_message(&mut self, message: &Message) -> Result<()> {
+        if !self.filter.matches(message) {
+            return Ok(());
+        }
+
+        // keep any partial line of legacy text ahead of the message
+        let pending = std::mem::take(&mut self.pending);
+        self.emit(&pending, &pending)?;
+
+        let (screen, log) = self.render(&format!("{}\n", message.payload));
+        let screen = message_prefix(message, self.terminal) + &screen;
+        let log = message_prefix(message, false) + &log;
+        self.emit(screen.as_bytes(), log.as_bytes())
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        for packet in self.decoder.decode(data) {
+            match packet {
+                Packet::Text(text) => self.write_text(&text)?,
+                Packet::Message(message) => self.write_message(&message)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out any incomplete line left over when the connection closes
+    pub fn finish(&mut self) -> Result<()> {
+        let remaining = self.decoder.finish();
+        self.write_text(&remaining)?;
+
+        let pending = std::mem::take(&mut self.pending);
+        self.emit(&pending, &pending)
+    }
+
+    pub fn separator(&mut self, message: &str) -> Result<()> {
+        let line = format!(
+            "--- {message} at {} ---\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        self.emit(line.as_bytes(), line.as_bytes())
+    }
+}
+
+fn create_spinner(message: &str) -> Result<ProgressBar> {
+    let progress_bar = ProgressBar::new_spinner();
+    let template = ProgressStyle::with_template(&format!("{message} {{spinner}}"))?
+        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]);
+    progress_bar.set_style(template);
+    Ok(progress_bar)
+}
+
+/// Connects to the target, retrying with backoff when `watch` is set
+fn connect(address: SocketAddr, watch: bool, quiet: bool) -> Result<Socket> {
+    let message = if watch {
+        "Waiting for target..."
+    } else {
+        "Attaching..."
+    };
+    let progress = match quiet {
+        true => ProgressBar::hidden(),
+        false => create_spinner(message)?,
+    };
+    progress.enable_steady_tick(Duration::from_millis(120));
+
+    let mut delay = RETRY_DELAY;
+    loop {
+        match Socket::new(address) {
+            Ok(socket) => {
+                progress.finish_with_message("Attached.");
+                return Ok(socket);
+            }
+            Err(e) if !watch => {
+                progress.abandon_with_message("Failed to connect.");
+                return Err(e.into());
+            }
+            Err(_) => {
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
+}
+
+pub fn attach(
+    address: SocketAddr,
+    output: &mut Output,
+    watch: bool,
+    remote: &Remote,
+) -> Result<()> {
+    let separators = watch || output.is_interactive();
+    let mut session = 1;
+    loop {
+        let mut socket = connect(address, watch, output.is_interactive())?;
+        if separators {
+            output.separator(&format!("session {session} started"))?;
+        }
+        *remote
+            .lock()
+            .map_err(|_| anyhow!("Console lock poisoned"))? = Some(socket.writer()?);
+
+        loop {
+            match socket.read() {
+                Ok(Some(data)) => output.write(data)?,
+                Ok(None) => break,
+                // the console dropping off the network is just another restart
+                Err(_) if watch => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        output.finish()?;
+        *remote
+            .lock()
+            .map_err(|_| anyhow!("Console lock poisoned"))? = None;
+
+        if separators {
+            output.separator(&format!("session {session} ended"))?;
+        }
+        if !watch {
+            return Ok(());
+        }
+        session += 1;
+    }
+}
@@ -1,11 +1,20 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use crate::config::bundle::{Build, BundleConfig, CONFIG_NAME, Metadata, PlatformTarget};
-use crate::models::bundle::Bundle;
+use crate::config::bundle::{
+    Build, BundleConfig, CONFIG_NAME, DEFAULT_RUNTIME, Metadata, PlatformTarget,
+};
+use crate::models::bundle::{Bundle, Listing, OUTPUT_DIR, WriteOptions};
+use crate::models::compat::Report;
+use crate::models::lint::{Linter, Severity};
+use crate::platforms::icon;
+use crate::services::package::package_target;
 use crate::{confirm, multiselect, txt};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+
+/// Icons converted from `metadata.icon` are kept here between builds
+const ICON_CACHE: &str = ".icons";
 
 pub fn generate_bundle_config() -> Result<()> {
     let mut metadata = Metadata {
@@ -13,20 +22,30 @@ pub fn generate_bundle_config() -> Result<()> {
         author: txt!("Enter author name:", "SuperAuthor"),
         description: txt!("Enter game description:", "SuperDescription"),
         version: txt!("Enter game version:", "0.1.0"),
+        icon: None,
         icons: HashMap::new(),
     };
 
     let targets = vec!["ctr", "hac", "cafe"];
 
-    let build = Build {
+    let mut build = Build {
         targets: multiselect!("Select build targets:", targets),
         source: txt!("Enter source directory:", "src"),
         packaged: confirm!(
             "Package the builds?",
             "If yes, the targets will be compiled to binary formats"
         ),
+        runtime: String::from(DEFAULT_RUNTIME),
+        gitignore: false,
+        compile_lua: false,
+        minify_lua: false,
+        compression: BTreeMap::new(),
     };
 
+    if build.packaged {
+        build.runtime = txt!("Enter LÖVE Potion runtime directory:", DEFAULT_RUNTIME);
+    }
+
     if build.has_target(PlatformTarget::Ctr) {
         let path = txt!("Nintendo 3DS icon path:", "icon48.png");
         metadata.set_icon(PlatformTarget::Ctr, &path);
@@ -50,16 +69,205 @@ pub fn generate_bundle_config() -> Result<()> {
     Ok(())
 }
 
-pub fn zip_bundle() -> Result<()> {
+/// Validates the icon of every target, converting `metadata.icon` for the
+/// targets that don't have their own unless `dry_run`
+fn resolve_icons(
+    config: &BundleConfig,
+    targets: &[PlatformTarget],
+    out_dir: &Path,
+    dry_run: bool,
+) -> Result<HashMap<PlatformTarget, PathBuf>> {
+    let cache = out_dir.join(ICON_CACHE);
+    let mut icons = HashMap::new();
+    let mut errors = Vec::new();
+
+    for &platform in targets {
+        let metadata = &config.metadata;
+        let result = match (metadata.icons.get(&platform), &metadata.icon) {
+            (Some(path), _) => icon::load(platform, Path::new(path)).map(|_| PathBuf::from(path)),
+            (None, Some(source)) if dry_run => icon::check(platform, Path::new(source), &cache),
+            (None, Some(source)) => icon::convert(platform, Path::new(source), &cache),
+            (None, None) => continue,
+        };
+
+        match result {
+            Ok(path) => {
+                icons.insert(platform, path);
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("Invalid icons:\n  {}", errors.join("\n  "));
+    }
+    Ok(icons)
+}
+
+fn select_targets(
+    config: &BundleConfig,
+    targets: Vec<PlatformTarget>,
+) -> Result<Vec<PlatformTarget>> {
+    let targets = match targets.is_empty() {
+        true => config.build.targets.clone(),
+        false => targets,
+    };
+    if let Some(target) = targets.iter().find(|&&t| !config.build.has_target(t)) {
+        bail!("{target} is not one of the targets in {CONFIG_NAME}");
+    }
+    Ok(targets)
+}
+
+fn print_listing(platform: PlatformTarget, listing: &Listing) {
+    println!("{platform}:");
+    for entry in listing.included.iter().filter(|entry| !entry.is_dir) {
+        println!("  + {}", entry.zip_path.display());
+    }
+    for excluded in &listing.excluded {
+        println!("  - {} ({})", excluded.path.display(), excluded.reason);
+    }
+}
+
+/// Checks every Lua file going into the bundle, failing on syntax errors and
+/// warning about what may not work on `targets`, with a table of the features
+/// each target supports if `compat`
+fn check_sources(
+    config: &BundleConfig,
+    targets: &[PlatformTarget],
+    out_dir: &Path,
+    compat: bool,
+) -> Result<()> {
+    let Some(&platform) = targets.first() else {
+        bail!("No targets in {CONFIG_NAME}");
+    };
+    // the Lua files are the same for every target
+    let listing = Bundle::new(config, platform, None, out_dir)?.list()?;
+    let files: Vec<_> = listing
+        .included
+        .iter()
+        .filter(|entry| !entry.is_dir)
+        .filter(|entry| entry.zip_path.extension().is_some_and(|ext| ext == "lua"))
+        .collect();
+    let modules: HashSet<String> = files
+        .iter()
+        .map(|entry| entry.zip_path.to_string_lossy().replace('\\', "/"))
+        .collect();
+
+    let linter = Linter::new()?;
     let cwd = std::env::current_dir()?;
-    if !Path::new(CONFIG_NAME).exists() {
-        anyhow::bail!("Could not find `{CONFIG_NAME}` in `{}`", cwd.display());
+    let mut diagnostics = Vec::new();
+    let mut report = Report::default();
+    for entry in &files {
+        let source = String::from_utf8_lossy(&std::fs::read(&entry.path)?).into_owned();
+        let path = entry.path.strip_prefix(&cwd).unwrap_or(&entry.path);
+        match linter.syntax(path, &source) {
+            Some(error) => diagnostics.push(error),
+            None => diagnostics.extend(linter.lint(path, &source, targets, &modules)),
+        }
+        for api in linter.apis(&source) {
+            report.add_api(&api, path);
+        }
     }
 
-    let config_contents = std::fs::read_to_string(CONFIG_NAME)?;
-    let config = toml::from_str::<BundleConfig>(&config_contents)?;
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
 
-    let mut bundle = Bundle::new(config)?;
-    bundle.add_tree()?;
-    bundle.finish()
+    if compat {
+        for entry in listing.included.iter().filter(|entry| !entry.is_dir) {
+            report.add_asset(entry.path.strip_prefix(&cwd).unwrap_or(&entry.path));
+        }
+        println!();
+        report.print(targets);
+        println!();
+    }
+    if errors > 0 {
+        bail!("Found {errors} syntax error(s) in the Lua sources");
+    }
+    println!("Checked {} Lua file(s), {warnings} warning(s)", files.len());
+    Ok(())
+}
+
+/// Checks the Lua sources of the targets, defaulting to all of them
+pub fn check_bundle(targets: Vec<PlatformTarget>, compat: bool) -> Result<()> {
+    let config = BundleConfig::load()?;
+    let targets = select_targets(&config, targets)?;
+    check_sources(&config, &targets, Path::new(OUTPUT_DIR), compat)
+}
+
+/// How `bundle create` builds the bundles.
+#[derive(Default, Clone)]
+pub struct Options {
+    pub out_dir: Option<PathBuf>,
+    /// Defaults to all of the targets in the config
+    pub targets: Vec<PlatformTarget>,
+    /// Only list what would be bundled
+    pub dry_run: bool,
+    /// Check the Lua sources first
+    pub check: bool,
+    pub write: WriteOptions,
+}
+
+impl Options {
+    /// Where the bundles are written, relative to the project unless absolute
+    pub fn out_dir(&self) -> PathBuf {
+        self.out_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(OUTPUT_DIR))
+    }
+}
+
+/// Lists what each target's bundle would contain, without writing anything
+pub fn list_bundle(targets: Vec<PlatformTarget>) -> Result<()> {
+    zip_bundle(Options {
+        targets,
+        dry_run: true,
+        ..Options::default()
+    })
+}
+
+/// Creates `<out_dir>/<target>/game.zip` for each target, and packages it
+/// if configured, returning what was built for each target
+pub fn build_bundles(options: Options) -> Result<Vec<(PlatformTarget, PathBuf)>> {
+    let config = BundleConfig::load()?;
+    let out_dir = options.out_dir();
+    let targets = select_targets(&config, options.targets)?;
+
+    if options.check && !options.dry_run {
+        check_sources(&config, &targets, &out_dir, false)?;
+    }
+
+    let icons = resolve_icons(&config, &targets, &out_dir, options.dry_run)?;
+    let mut outputs = Vec::new();
+    for platform in targets {
+        let icon = icons.get(&platform).map(PathBuf::as_path);
+        let bundle = Bundle::new(&config, platform, icon, &out_dir)?;
+        let listing = bundle.list()?;
+
+        if options.dry_run {
+            print_listing(platform, &listing);
+            continue;
+        }
+        let archive = bundle.write(&listing, options.write)?;
+
+        let output = match config.build.packaged {
+            true => package_target(&config, platform, icon, &archive)?,
+            false => archive,
+        };
+        outputs.push((platform, output));
+    }
+    Ok(outputs)
+}
+
+/// Creates `<out_dir>/<target>/game.zip` for each target
+pub fn zip_bundle(options: Options) -> Result<()> {
+    build_bundles(options)?;
+    Ok(())
 }
@@ -0,0 +1,285 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Completer, Editor, ExternalPrinter, Helper, Highlighter, Hinter};
+
+use crate::config::app::Config;
+use crate::models::socket::SocketWriter;
+
+const PROMPT: &str = "> ";
+const HISTORY_NAME: &str = "history.txt";
+
+/// The connection the console sends to, if the target is currently attached.
+pub type Remote = Arc<Mutex<Option<SocketWriter>>>;
+
+/// Keeps reading lines until the Lua chunk is complete
+#[derive(Completer, Helper, Highlighter, Hinter)]
+struct LuaHelper;
+
+impl Validator for LuaHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        match is_incomplete(ctx.input()) {
+            true => Ok(ValidationResult::Incomplete),
+            false => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
+/// Finds the end of a long bracket (`]]`, `]==]`, ...) opened at `start`
+fn skip_long_bracket(chunk: &[u8], start: usize) -> Option<usize> {
+    let level = chunk[start + 1..]
+        .iter()
+        .take_while(|&&b| b == b'=')
+        .count();
+    if chunk.get(start + 1 + level) != Some(&b'[') {
+        return None;
+    }
+
+    let close = [b"]".as_slice(), &b"=".repeat(level), b"]"].concat();
+    let body = start + level + 2;
+    chunk[body..]
+        .windows(close.len())
+        .position(|window| window == close)
+        .map(|end| body + end + close.len())
+        .or(Some(usize::MAX))
+}
+
+/// Whether `chunk` still has open blocks, brackets or long strings
+fn is_incomplete(chunk: &str) -> bool {
+    let bytes = chunk.as_bytes();
+    let mut depth = 0i32;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        match byte {
+            b'-' if bytes.get(index + 1) == Some(&b'-') => {
+                index += 2;
+                if bytes.get(index) == Some(&b'[')
+                    && let Some(end) = skip_long_bracket(bytes, index)
+                {
+                    index = end;
+                    continue;
+                }
+                while index < bytes.len() && bytes[index] != b'\n' {
+                    index += 1;
+                }
+            }
+            b'[' if matches!(bytes.get(index + 1), Some(b'[' | b'=')) => {
+                match skip_long_bracket(bytes, index) {
+                    Some(end) => index = end,
+                    None => {
+                        depth += 1;
+                        index += 1;
+                    }
+                }
+            }
+            b'"' | b'\'' => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != byte && bytes[index] != b'\n' {
+                    if bytes[index] == b'\\' {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                index += 1;
+            }
+            b'(' | b'{' | b'[' => {
+                depth += 1;
+                index += 1;
+            }
+            b')' | b'}' | b']' => {
+                depth -= 1;
+                index += 1;
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                let start = index;
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'_')
+                {
+                    index += 1;
+                }
+                match &chunk[start..index] {
+                    "function" | "do" | "then" | "repeat" => depth += 1,
+                    "end" | "until" | "elseif" => depth -= 1,
+                    _ => {}
+                }
+            }
+            _ => index += 1,
+        }
+    }
+
+    // an unterminated long string or comment runs off the end of the chunk
+    index == usize::MAX || depth > 0
+}
+
+/// Ends the console once the reader finishes, even while it's prompting.
+#[derive(Clone, Default)]
+pub struct Interrupter {
+    finished: Arc<AtomicBool>,
+    /// Whether the console woke its own prompt, rather than the user pressing Ctrl-C
+    waking: Arc<AtomicBool>,
+}
+
+impl Interrupter {
+    pub fn interrupt(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+        self.wake();
+    }
+
+    /// rustyline turns SIGINT into `ReadlineError::Interrupted` while it
+    /// prompts, the console's handler knows to ignore this one
+    #[cfg(unix)]
+    fn wake(&self) {
+        self.waking.store(true, Ordering::SeqCst);
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGINT);
+        }
+    }
+
+    /// The console reads key presses on Windows, so it's sent Ctrl-C as one,
+    /// which rustyline turns into `ReadlineError::Interrupted`
+    #[cfg(windows)]
+    fn wake(&self) {
+        use windows_sys::Win32::System::Console::{
+            GetStdHandle, INPUT_RECORD, KEY_EVENT, LEFT_CTRL_PRESSED, STD_INPUT_HANDLE,
+            WriteConsoleInputW,
+        };
+
+        const VK_C: u16 = b'C' as u16;
+        const CTRL_C: u16 = 0x03;
+
+        unsafe {
+            let mut record: INPUT_RECORD = std::mem::zeroed();
+            record.EventType = KEY_EVENT as u16;
+            record.Event.KeyEvent.bKeyDown = 1;
+            record.Event.KeyEvent.wRepeatCount = 1;
+            record.Event.KeyEvent.wVirtualKeyCode = VK_C;
+            record.Event.KeyEvent.uChar.UnicodeChar = CTRL_C;
+            record.Event.KeyEvent.dwControlKeyState = LEFT_CTRL_PRESSED;
+
+            let mut written = 0;
+            WriteConsoleInputW(GetStdHandle(STD_INPUT_HANDLE), &record, 1, &mut written);
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn wake(&self) {}
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Whether a Ctrl-C came from `wake`, expecting just the one
+    fn was_woken(&self) -> bool {
+        self.waking.swap(false, Ordering::SeqCst)
+    }
+}
+
+pub struct Console {
+    editor: Editor<LuaHelper, DefaultHistory>,
+    history: PathBuf,
+    interrupter: Interrupter,
+}
+
+impl Console {
+    pub fn new() -> Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(LuaHelper));
+
+        let history = match Config::path()?.parent() {
+            Some(parent) => parent.join(HISTORY_NAME),
+            None => PathBuf::from(HISTORY_NAME),
+        };
+        // there is no history on the first run
+        let _ = editor.load_history(&history);
+
+        let interrupter = Interrupter::default();
+        let woken = interrupter.clone();
+        ctrlc::set_handler(move || {
+            if !woken.was_woken() {
+                std::process::exit(130);
+            }
+        })?;
+
+        Ok(Self {
+            editor,
+            history,
+            interrupter,
+        })
+    }
+
+    /// For the reader to end the console with once it finishes
+    pub fn interrupter(&self) -> Interrupter {
+        self.interrupter.clone()
+    }
+
+    /// Only available when stdin is a terminal
+    pub fn printer(&mut self) -> Option<Box<dyn ExternalPrinter + Send>> {
+        let printer = self.editor.create_external_printer().ok()?;
+        Some(Box::new(printer))
+    }
+
+    fn is_detached(remote: &Remote) -> Result<bool> {
+        let remote = remote
+            .lock()
+            .map_err(|_| anyhow!("Console lock poisoned"))?;
+        Ok(remote.is_none())
+    }
+
+    /// Forwards chunks of Lua to the target until stdin closes or the reader finishes
+    pub fn run(&mut self, remote: &Remote, reader: &JoinHandle<Result<()>>) -> Result<()> {
+        // hold the prompt back until the first session is attached
+        while !reader.is_finished() && Self::is_detached(remote)? {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        loop {
+            let finished = || self.interrupter.is_finished() || reader.is_finished();
+            if finished() {
+                break;
+            }
+
+            let chunk = match self.editor.readline(PROMPT) {
+                Ok(chunk) => chunk,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            if finished() {
+                break;
+            }
+
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            self.editor.add_history_entry(chunk.as_str())?;
+
+            let mut remote = remote
+                .lock()
+                .map_err(|_| anyhow!("Console lock poisoned"))?;
+            match remote.as_mut() {
+                Some(writer) => {
+                    if let Err(e) = writer.send(&chunk) {
+                        eprintln!("Failed to send: {e}");
+                    }
+                }
+                None => eprintln!("Not attached, input discarded"),
+            }
+        }
+
+        if let Some(parent) = self.history.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.editor.save_history(&self.history)?;
+        Ok(())
+    }
+}
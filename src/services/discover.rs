@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+use inquire::MultiSelect;
+
+use crate::config::app::{Config, Connection};
+use crate::platforms::discover::discover;
+use crate::txt;
+
+fn create_spinner() -> Result<ProgressBar> {
+    let progress_bar = ProgressBar::new_spinner();
+    let template = ProgressStyle::with_template("Searching for devices... {spinner}")?
+        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]);
+    progress_bar.set_style(template);
+    Ok(progress_bar)
+}
+
+pub fn discover_devices(config: &mut Config, timeout: u64) -> Result<()> {
+    let progress = create_spinner()?;
+    progress.enable_steady_tick(Duration::from_millis(120));
+    let devices = discover(config.get_port(), Duration::from_secs(timeout));
+    progress.finish_and_clear();
+
+    let devices = devices?;
+    if devices.is_empty() {
+        println!("No devices found");
+        return Ok(());
+    }
+
+    println!("{:<15} {:<5} Services", "Address", "Type");
+    for device in &devices {
+        println!("{device}");
+    }
+
+    let selected = MultiSelect::new("Select devices to save:", devices).prompt()?;
+    for device in selected {
+        let default = device
+            .platform
+            .map_or("device".to_string(), |p| p.to_string());
+        let name = txt!(&format!("Name for {}:", device.address), &default);
+        let connection = Connection {
+            platform: device.platform,
+            ..Connection::new(device.address.to_string())
+        };
+        config.add(&name, connection)?;
+    }
+    Ok(())
+}
@@ -1 +1,7 @@
+pub mod attach;
 pub mod bundle;
+pub mod console;
+pub mod discover;
+pub mod package;
+pub mod send;
+pub mod watch;
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+use crate::config::bundle::{BundleConfig, PlatformTarget};
+use crate::platforms::metadata;
+use crate::platforms::package::{build_wuhb, extension, fuse_3dsx, fuse_nro, runtime_name};
+
+/// LÖVE Potion on the Wii U loads the game from its WUHB content folder
+const CAFE_GAME_NAME: &str = "game.love";
+const CAFE_ICON_NAME: &str = "iconTex.tga";
+/// The Homebrew Launcher reads it from the app's folder, next to the package
+const CAFE_META_NAME: &str = "meta.xml";
+/// Used when nothing is left of the game's name once made safe for a path
+const DEFAULT_STEM: &str = "game";
+
+/// `name` with anything that isn't allowed in a file name replaced, so the
+/// package can't end up outside of its directory
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, and `..` is the parent
+    let stem = stem.trim_matches(|c| c == '.' || c == ' ');
+    match stem.is_empty() {
+        true => String::from(DEFAULT_STEM),
+        false => String::from(stem),
+    }
+}
+
+struct Packager<'a> {
+    config: &'a BundleConfig,
+    icon: Option<&'a Path>,
+    game: &'a [u8],
+    runtime: PathBuf,
+    output: PathBuf,
+    scratch: PathBuf,
+}
+
+impl Packager<'_> {
+    fn runtime(&self, platform: PlatformTarget) -> Result<PathBuf> {
+        let path = self.runtime.join(runtime_name(platform));
+        if !path.is_file() {
+            bail!("Could not find the {platform} runtime at {path:#?}");
+        }
+        Ok(path)
+    }
+
+    fn icon(&self, platform: PlatformTarget) -> Result<PathBuf> {
+        match self.icon {
+            Some(path) => Ok(path.to_path_buf()),
+            None => {
+                bail!("No icon for {platform}, set `metadata.icons.{platform}` or `metadata.icon`")
+            }
+        }
+    }
+
+    fn output_path(&self, platform: PlatformTarget) -> PathBuf {
+        let stem = file_stem(&self.config.metadata.name);
+        self.output.join(format!("{stem}.{}", extension(platform)))
+    }
+
+    fn package_ctr(&self) -> Result<Vec<u8>> {
+        let smdh = metadata::smdh(&self.config.metadata, &self.icon(PlatformTarget::Ctr)?)?;
+        let runtime = fs::read(self.runtime(PlatformTarget::Ctr)?)?;
+        fuse_3dsx(&runtime, &smdh, self.game)
+    }
+
+    fn package_hac(&self) -> Result<Vec<u8>> {
+        let nacp = metadata::nacp(&self.config.metadata);
+        let icon = metadata::hac_icon(&self.icon(PlatformTarget::Hac)?)?;
+        let runtime = fs::read(self.runtime(PlatformTarget::Hac)?)?;
+        fuse_nro(&runtime, &icon, &nacp, self.game)
+    }
+
+    fn package_cafe(&self, output: &Path) -> Result<()> {
+        let content = self.scratch.join("content");
+        fs::create_dir_all(&content)?;
+        fs::write(content.join(CAFE_GAME_NAME), self.game)?;
+
+        let metadata = &self.config.metadata;
+        let icon = self.scratch.join(CAFE_ICON_NAME);
+        fs::write(
+            &icon,
+            metadata::cafe_icon(&self.icon(PlatformTarget::Cafe)?)?,
+        )?;
+        fs::write(
+            self.output.join(CAFE_META_NAME),
+            metadata::meta_xml(metadata),
+        )?;
+
+        build_wuhb(
+            &self.runtime(PlatformTarget::Cafe)?,
+            &content,
+            output,
+            &metadata.name,
+            &metadata.author,
+            &icon,
+        )
+    }
+
+    fn package(&self, platform: PlatformTarget) -> Result<PathBuf> {
+        let output = self.output_path(platform);
+        match platform {
+            PlatformTarget::Ctr => fs::write(&output, self.package_ctr()?)?,
+            PlatformTarget::Hac => fs::write(&output, self.package_hac()?)?,
+            PlatformTarget::Cafe => self.package_cafe(&output)?,
+        }
+        println!("{} created successfully.", output.display());
+        Ok(output)
+    }
+}
+
+/// Fuses the bundle at `game` onto the LÖVE Potion runtime of `platform`,
+/// writing the result next to it
+pub fn package_target(
+    config: &BundleConfig,
+    platform: PlatformTarget,
+    icon: Option<&Path>,
+    game: &Path,
+) -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    let output = match game.parent() {
+        Some(parent) => parent.to_path_buf(),
+        None => cwd.clone(),
+    };
+    let data = fs::read(game)?;
+
+    let packager = Packager {
+        config,
+        icon,
+        game: &data,
+        runtime: cwd.join(&config.build.runtime),
+        output,
+        scratch: std::env::temp_dir().join(format!("nestcli-{}", std::process::id())),
+    };
+
+    fs::create_dir_all(&packager.scratch)?;
+    let result = packager.package(platform);
+    // a failed cleanup shouldn't hide how packaging went
+    if let Err(e) = fs::remove_dir_all(&packager.scratch) {
+        eprintln!("Failed to remove {}: {e}", packager.scratch.display());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_names_inside_the_output_directory() {
+        assert_eq!(file_stem("Super Game"), "Super Game");
+        assert_eq!(file_stem("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(file_stem("C:\\Games\\a?b"), "C__Games_a_b");
+        assert_eq!(file_stem("Name\n."), "Name_");
+        assert_eq!(file_stem(".."), DEFAULT_STEM);
+        assert_eq!(file_stem(""), DEFAULT_STEM);
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener};
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::config::app::Config;
+use crate::config::bundle::PlatformTarget;
+use crate::platforms::detect::{Container, detect_platform};
+use crate::platforms::link::{self, NXLINK_CLIENT_PORT, NXLINK_SERVER_ARG, Protocol, Upload};
+use crate::services::attach::{Filter, Output};
+
+fn create_progress(length: usize, protocol: Protocol) -> Result<ProgressBar> {
+    let progress_bar = ProgressBar::new(length as u64);
+    let template = ProgressStyle::with_template("{msg} [{bar:40}] {bytes}/{total_bytes}")?
+        .progress_chars("=> ");
+    progress_bar.set_style(template);
+    progress_bar.set_message(format!("Sending via {}", protocol.name()));
+    Ok(progress_bar)
+}
+
+fn find_platform(filepath: &Path, platform: Option<PlatformTarget>) -> Result<PlatformTarget> {
+    if let Some(platform) = platform {
+        return Ok(platform);
+    }
+
+    let detection = detect_platform(filepath)?;
+    if detection.container == Container::Elf {
+        bail!("{filepath:#?} is a plain ELF, send the 3DSX, NRO, RPX or WUHB built from it");
+    }
+    Ok(detection.platform)
+}
+
+/// Prints the stdio of a program launched with nxlink's server enabled
+fn serve_stdio(listener: TcpListener) -> Result<()> {
+    println!("Waiting for stdio on port {NXLINK_CLIENT_PORT}...");
+    let (mut stream, _) = listener.accept()?;
+
+    let mut output = Output::new(None, None, None, 0, Filter::default());
+    let mut buffer = vec![0; 0x1000];
+    loop {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            n => output.write(&buffer[..n])?,
+        }
+    }
+    output.finish()
+}
+
+pub fn send_file(
+    config: &Config,
+    target: &str,
+    filepath: &Path,
+    args: &[String],
+    platform: Option<PlatformTarget>,
+    server: bool,
+) -> Result<()> {
+    let connection = config.resolve(target);
+    let platform = find_platform(filepath, platform)?;
+    if let Some(expected) = connection.platform
+        && expected != platform
+    {
+        bail!("{target} is a {expected} connection but {filepath:#?} is for {platform}");
+    }
+    // the launchers listen on fixed ports, only the host comes from the connection
+    let address = connection.socket_addr(0)?.ip();
+    let protocol = Protocol::for_platform(platform);
+
+    if server && protocol != Protocol::NxLink {
+        bail!("The stdio server is only supported by nxlink");
+    }
+
+    let name = match filepath.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => bail!("Invalid file name: {filepath:#?}"),
+    };
+    let data = std::fs::read(filepath)?;
+
+    // listen before launching so the console can connect back straight away
+    let mut args = args.to_vec();
+    let listener = match server {
+        true => {
+            args.push(NXLINK_SERVER_ARG.to_string());
+            let host: IpAddr = match address {
+                IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+                IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+            };
+            Some(TcpListener::bind((host, NXLINK_CLIENT_PORT))?)
+        }
+        false => None,
+    };
+
+    let upload = Upload {
+        name,
+        data: &data,
+        args: &args,
+    };
+    let progress = create_progress(data.len(), protocol)?;
+    link::send(protocol, address, &upload, &progress)?;
+    println!("{name} sent to {target} ({address})");
+
+    match listener {
+        Some(listener) => serve_stdio(listener),
+        None => Ok(()),
+    }
+}
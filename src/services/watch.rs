@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::app::Config;
+use crate::config::bundle::{BundleConfig, CONFIG_NAME, PlatformTarget};
+use crate::models::bundle::WriteOptions;
+use crate::services::bundle::{Options, build_bundles};
+use crate::services::send::send_file;
+
+/// Changes closer together than this are rebuilt once
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// `path` without symlinks or `..`, as far as it exists, so it can be compared
+/// with the paths of events
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    // deleted files can't be resolved, their directory usually can
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => match parent.canonicalize() {
+            Ok(parent) => parent.join(name),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+/// What a rebuild depends on: the config, the icons and the source directory.
+struct Inputs {
+    /// Canonical, as are the paths compared with it
+    cwd: PathBuf,
+    /// Where the bundles are written, which is never an input
+    out_dir: PathBuf,
+    config: Option<BundleConfig>,
+}
+
+impl Inputs {
+    fn icons(&self) -> Vec<PathBuf> {
+        let Some(config) = &self.config else {
+            return Vec::new();
+        };
+        let metadata = &config.metadata;
+        metadata
+            .icons
+            .values()
+            .chain(&metadata.icon)
+            .map(|icon| self.cwd.join(icon))
+            .collect()
+    }
+
+    /// The directories to watch, the project's own for the config and icons
+    fn directories(&self) -> Vec<(PathBuf, RecursiveMode)> {
+        let mut directories = vec![(self.cwd.clone(), RecursiveMode::NonRecursive)];
+        for icon in self.icons() {
+            if let Some(parent) = icon.parent() {
+                directories.push((parent.to_path_buf(), RecursiveMode::NonRecursive));
+            }
+        }
+        if let Some(config) = &self.config {
+            directories.push((
+                self.cwd.join(&config.build.source),
+                RecursiveMode::Recursive,
+            ));
+        }
+        directories.sort();
+        directories.dedup();
+        directories
+    }
+
+    /// Whether `path`, canonical, is one of the inputs
+    fn is_input(&self, path: &Path) -> bool {
+        // bundles are written under the project, often under the source
+        if path.starts_with(canonical(&self.cwd.join(&self.out_dir))) {
+            return false;
+        }
+        if path == canonical(&self.cwd.join(CONFIG_NAME))
+            || self.icons().iter().any(|icon| canonical(icon) == path)
+        {
+            return true;
+        }
+        match &self.config {
+            Some(config) => path.starts_with(canonical(&self.cwd.join(&config.build.source))),
+            None => false,
+        }
+    }
+}
+
+/// Watches the directories `inputs` depends on instead of the `watched` ones
+fn rewatch(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut Vec<PathBuf>,
+    inputs: &Inputs,
+) -> Result<()> {
+    for directory in watched.drain(..) {
+        // gone directories are no longer watched anyway
+        let _ = watcher.unwatch(&directory);
+    }
+    for (directory, mode) in inputs.directories() {
+        if directory.is_dir() {
+            watcher.watch(&directory, mode)?;
+            watched.push(directory);
+        }
+    }
+    Ok(())
+}
+
+/// Blocks until an input changes, then until the changes settle, returning
+/// the changed paths
+fn wait_for_changes(
+    receiver: &Receiver<notify::Result<Event>>,
+    inputs: &Inputs,
+) -> Result<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    loop {
+        let event = match changed.is_empty() {
+            true => receiver.recv()?,
+            false => match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => return Ok(changed),
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        let event = event?;
+        if matches!(event.kind, EventKind::Access(_)) {
+            continue;
+        }
+        let paths = event.paths.iter().map(|path| canonical(path));
+        changed.extend(paths.filter(|path| inputs.is_input(path)));
+    }
+}
+
+/// Sends the build for the connection's platform, or the only one built
+fn send(config: &Config, connection: &str, outputs: &[(PlatformTarget, PathBuf)]) -> Result<()> {
+    let platform = config.resolve(connection).platform;
+    let output = match platform {
+        Some(platform) => outputs.iter().find(|(target, _)| *target == platform),
+        None if outputs.len() == 1 => outputs.first(),
+        None => bail!("Set the platform of {connection} or watch a single --target to send"),
+    };
+
+    match output {
+        Some((platform, path)) => send_file(config, connection, path, &[], Some(*platform), false),
+        None => bail!("Nothing was built for {connection}"),
+    }
+}
+
+fn rebuild(config: &Config, options: &Options, connection: Option<&str>) -> Result<()> {
+    let outputs = build_bundles(options.clone())?;
+
+    let Some(connection) = connection else {
+        return Ok(());
+    };
+    if !BundleConfig::load()?.build.packaged {
+        bail!("Only packaged builds can be sent, set `build.packaged` in {CONFIG_NAME}");
+    }
+    send(config, connection, &outputs)
+}
+
+/// Rebuilds the bundles whenever the sources, icons or config change,
+/// sending the build to `connection` after each rebuild if given
+pub fn watch_bundle(
+    config: &Config,
+    targets: Vec<PlatformTarget>,
+    connection: Option<String>,
+    fast: bool,
+) -> Result<()> {
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mut watched = Vec::new();
+    let options = Options {
+        targets,
+        check: true,
+        write: WriteOptions {
+            incremental: true,
+            fast,
+        },
+        ..Options::default()
+    };
+    let mut inputs = Inputs {
+        cwd: std::env::current_dir()?.canonicalize()?,
+        out_dir: options.out_dir(),
+        config: None,
+    };
+
+    loop {
+        match BundleConfig::load() {
+            Ok(bundle_config) => inputs.config = Some(bundle_config),
+            Err(e) => eprintln!("{e}"),
+        }
+        rewatch(&mut watcher, &mut watched, &inputs)?;
+
+        if let Err(e) = rebuild(config, &options, connection.as_deref()) {
+            eprintln!("Rebuild failed: {e}");
+        }
+        println!("Watching for changes, press Ctrl-C to stop...");
+
+        let changed = wait_for_changes(&receiver, &inputs)?;
+        for path in changed {
+            let path = path.strip_prefix(&inputs.cwd).unwrap_or(&path);
+            println!("Changed: {}", path.display());
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::bundle::{Build, Metadata};
+
+    #[test]
+    fn matches_paths_through_symlinks() {
+        let root = std::env::temp_dir().join(format!("nestcli-watch-{}", std::process::id()));
+        let project = root.join("project");
+        fs::create_dir_all(project.join("src/build")).unwrap();
+        fs::create_dir_all(project.join("out")).unwrap();
+        fs::write(project.join("src/main.lua"), "").unwrap();
+        std::os::unix::fs::symlink(&project, root.join("link")).unwrap();
+
+        let inputs = Inputs {
+            cwd: canonical(&root.join("link")),
+            out_dir: PathBuf::from("out"),
+            config: Some(BundleConfig {
+                build: Build {
+                    source: String::from("src"),
+                    ..Build::default()
+                },
+                metadata: Metadata::default(),
+            }),
+        };
+        let is_input = |path: &str| inputs.is_input(&canonical(&root.join(path)));
+
+        assert!(is_input("link/src/main.lua"));
+        assert!(is_input("project/src/main.lua"));
+        assert!(is_input("link/src/deleted.lua"));
+        assert!(is_input(&format!("link/{CONFIG_NAME}")));
+        // only the configured output directory is left out
+        assert!(is_input("link/src/build/level.lua"));
+        assert!(!is_input("link/out/ctr/game.zip"));
+        assert!(!is_input("link/docs/readme.md"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}